@@ -167,59 +167,77 @@ async fn websocket_handler(ws: WebSocketUpgrade, Query(query): Query<WebsocketQu
 async fn handle_socket(socket: WebSocket, Query(query): Query<WebsocketQueries>, state: Arc<RwLock<Broadcaster>>) {
     let (receiver, mut stream) = Broadcaster::configure(socket);
 
-    let broadcaster = Broadcaster::handle(&state, &query.room, &query.id, receiver).await;
+    // keep the membership while the socket is handled. When it's dropped, the connection is removed from it's room, even if this task panics.
+    let broadcaster = match Broadcaster::handle(&state, &query.room, &query.id, receiver).await {
+        Ok(broadcaster) => broadcaster,
+        Err(error) => {
+            println!("that error occured: {}", error);
+
+            return;
+        }
+    };
 
     while let Some(msg_result) = stream.next().await {
         match msg_result {
             Ok(message) => {
                 match message {
                     Message::Text(input) => {
-                        let mut broadcaster = broadcaster.write().await;
+                        let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(&query.room).broadcast(&input).await;
+                        if let Ok(room) = room {
+                            let _ = room.read().await.broadcast(&input).await;
+                        }
                     },
                     Message::Close(_) => {
                         // this is the old way of closing connections and making cleanup:
 
                         /*
-                        let mut broadcaster = broadcaster.write().await;
-
-                        let _ = broadcaster.remove_connection(query.id).unwrap().close().await;
+                        let _ = broadcaster.read().await.remove_connection(&query.id).await;
                         */
 
                         // the new way. This removes all the connections but keeps room open:
-                        /*let mut broadcaster = broadcaster.write().await;
+                        /*let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(query.room).close(None).await;*/
+                        if let Ok(room) = room {
+                            let _ = room.write().await.close(None).await;
+                        }*/
 
-                        // if you want to close a single connection without closing room, use that:
+                        // if you want to close a single connection without closing room, use that. The membership would remove it when the loop ends anyway, but that also sends the close frame back. It closes this connection itself, closing it by id with `.close_conn()` would also close the connection which replaced it, or the other ones with the same id:
 
-                        let mut broadcaster = broadcaster.write().await;
+                        let close_timeout = broadcaster.read().await.config().close_timeout;
+                        let _ = broadcaster.connection().clone().close(None, close_timeout).await;
 
-                        let _ = broadcaster.room(&query.room).close_conn(None, &query.id).await;
+                        broadcaster.release().await;
 
                         // this is the most proper way if you want to fully close a room:
 
-                        /*let mut broadcaster = broadcaster.write().await;
-                        
-                        let _ = broadcaster.remove_room(&query.room).await;*/
+                        /*let _ = Broadcaster::remove_room(&broadcaster, &query.room).await;*/
 
                         break;
                     },
                     Message::Ping(ping) => {
-                        let mut broadcaster = broadcaster.write().await;
+                        let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(&query.room).pong(&ping).await;
+                        if let Ok(room) = room {
+                            let _ = room.read().await.pong(&ping).await;
+                        }
                     },
                     Message::Pong(pong) => {
-                        let mut broadcaster = broadcaster.write().await;
+                        // the heartbeat closes the connections whose pongs are not recorded, .serve() does that by itself.
+                        broadcaster.connection().record_pong(&pong);
+
+                        let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(&query.room).ping(&pong).await;
+                        if let Ok(room) = room {
+                            let _ = room.read().await.ping(&pong).await;
+                        }
                     },
                     Message::Binary(binary) => {
-                        let mut broadcaster = broadcaster.write().await;
+                        let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(&query.room).binary(&binary).await;
+                        if let Ok(room) = room {
+                            let _ = room.read().await.binary(&binary).await;
+                        }
                     }
                 }
             },
@@ -3,7 +3,7 @@ use axum_wsb::typed::Broadcaster;
 #[cfg(feature = "typed")]
 use std::{fmt::Display, sync::Arc};
 #[cfg(feature = "typed")]
-use axum_typed_websockets::{WebSocket, WebSocketUpgrade, Message};
+use axum_typed_websockets::{WebSocket, WebSocketUpgrade};
 #[cfg(feature = "typed")]
 use axum_7_9::{Router, response::{Response, IntoResponse}, routing::get, extract::{State, Query}};
 #[cfg(feature = "typed")]
@@ -11,9 +11,7 @@ use tokio::sync::RwLock;
 #[cfg(feature = "typed")]
 use serde::{Serialize, Deserialize};
 #[cfg(feature = "typed")]
-use serde_json;
-#[cfg(feature = "typed")]
-use futures_util::{SinkExt, StreamExt, stream::{SplitStream, SplitSink}};
+use futures_util::StreamExt;
 
 #[tokio::main]
 async fn main() {
@@ -201,11 +199,17 @@ async fn websocket_handler(ws: WebSocketUpgrade<String, WebsocketInput>, Query(q
 
 #[cfg(feature = "typed")]
 async fn handle_socket(socket: WebSocket<String, WebsocketInput>, Query(query): Query<WebsocketQueries>, state: Arc<RwLock<Broadcaster<String, WebsocketInput>>>) {
-    use axum_7_9::extract::ws::Message;
-
     let (receiver, mut stream) = Broadcaster::configure(socket);
 
-    let broadcaster = Broadcaster::handle(&state, &query.room, &query.id, receiver).await;
+    // keep the membership while the socket is handled. When it's dropped, the connection is removed from it's room, even if this task panics.
+    let broadcaster = match Broadcaster::handle(&state, &query.room, &query.id, receiver).await {
+        Ok(broadcaster) => broadcaster,
+        Err(error) => {
+            println!("that error occured: {}", error);
+
+            return;
+        }
+    };
 
     while let Some(msg_result) = stream.next().await {
         match msg_result {
@@ -218,46 +222,54 @@ async fn handle_socket(socket: WebSocket<String, WebsocketInput>, Query(query):
                             message: input.message
                         };
 
-                        let mut broadcaster = broadcaster.write().await;
-
                         let output = serde_json::to_string(&output).unwrap();
 
-                        let _ = broadcaster.room(&query.room).broadcast(&output).await;
+                        let room = broadcaster.read().await.room(&query.room);
+
+                        if let Ok(room) = room {
+                            let _ = room.read().await.broadcast(&output).await;
+                        }
                     },
                     axum_typed_websockets::Message::Close(_) => {
                         // this is the old way of closing connections and making cleanup:
-                        /*let mut broadcaster = broadcaster.write().await;
-
-                        let _ = broadcaster.remove_connection(&query.id).unwrap().close().await;*/
+                        /*let _ = broadcaster.read().await.remove_connection(&query.id).await;*/
 
                         // the new way. This removes all the connections but keeps room open:
-                        /*let mut broadcaster = broadcaster.write().await;
+                        /*let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(&query.room).close(None).await;*/
+                        if let Ok(room) = room {
+                            let _ = room.write().await.close(None).await;
+                        }*/
 
-                        // if you want to close a single connection without closing room, use that:
+                        // if you want to close a single connection without closing room, use that. The membership would remove it when the loop ends anyway, but that also sends the close frame back. It closes this connection itself, closing it by id with `.close_conn()` would also close the connection which replaced it, or the other ones with the same id:
 
-                        let mut broadcaster = broadcaster.write().await;
+                        let close_timeout = broadcaster.read().await.config().close_timeout;
+                        let _ = broadcaster.connection().clone().close(None, close_timeout).await;
 
-                        let _ = broadcaster.room(&query.room).close_conn(None, &query.id).await;
+                        broadcaster.release().await;
 
                         // this is the most proper way if you want to fully close a room:
 
-                        /*let mut broadcaster = broadcaster.write().await;
-                        
-                        let _ = broadcaster.remove_room(&query.room).await;*/
+                        /*let _ = Broadcaster::remove_room(&broadcaster, &query.room).await;*/
                         
                         return;
                     },
                     axum_typed_websockets::Message::Ping(ping) => {
-                        let mut broadcaster = broadcaster.write().await;
+                        let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(&query.room).pong(&ping).await;
+                        if let Ok(room) = room {
+                            let _ = room.read().await.pong(&ping).await;
+                        }
                     },
                     axum_typed_websockets::Message::Pong(pong) => {
-                        let mut broadcaster = broadcaster.write().await;
+                        // the heartbeat closes the connections whose pongs are not recorded, .serve() does that by itself.
+                        broadcaster.connection().record_pong(&pong);
+
+                        let room = broadcaster.read().await.room(&query.room);
 
-                        let _ = broadcaster.room(&query.room).ping(&pong).await;
+                        if let Ok(room) = room {
+                            let _ = room.read().await.ping(&pong).await;
+                        }
                     }
                 }
             },
@@ -1,961 +1,5728 @@
-#[cfg(feature = "typed")]
-pub mod typed {
-    use serde::Serialize;
-    use futures_util::{Sink, Stream, SinkExt, stream::{SplitSink, SplitStream, StreamExt}};
-    use std::{fmt::Display, sync::Arc};
-    use tokio::sync::RwLock;
-    use axum_typed_websockets::{Message, WebSocket};
-    use axum_7_9::extract::ws::CloseFrame;
+use futures_util::{Sink, SinkExt};
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt, sync::{Arc, Mutex, MutexGuard, PoisonError}, time::{Duration, SystemTime}};
+use tokio::{sync::{watch, Notify}, task::AbortHandle, time::Instant};
 
-    /// main broadcaster for typed api.
-    #[derive(Debug)]
-    pub struct Broadcaster<T, S> {
-        pub rooms: Vec<Room<T, S>>
-    }
+/// capacity of the outbound queue that each connection gets by default.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 64;
 
-    /// room implementation.
-    #[derive(Debug)]
-    pub struct Room<T, S> {
-        pub id: String,
-        pub connections: Vec<Connection<T, S>>
-    }
+/// how long broadcasting methods wait for a place in the outbound queue of each connection by default.
+pub const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(1);
 
-    /// type for each individual connection.
-    #[derive(Debug)]
-    pub struct Connection<T, S> {
-        pub id: String,
-        pub receiver: SplitSink<WebSocket<T, S>, Message<T>>
-    }
+/// how long closing methods wait for a connection to write it's pending messages and the close frame by default.
+pub const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
-    impl<T: Display, S: Display> Connection<T, S> where SplitSink<WebSocket<T, S>, Message<T>>: Sink<Message<T>> + Unpin {
-        /// create a connection:
-        pub fn create(id: String, receiver: SplitSink<WebSocket<T, S>, Message<T>>) -> Self {
-            Self {
-                id, 
-                receiver
-            }
-        }
+/// how often the heartbeat pings the connections by default.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
 
-        /// send the text message.
-        pub async fn send(&mut self, message: T) where T: Clone {
-            let _ = self.receiver.send(Message::Item(message)).await;
-        }
+/// how often the reaper checks the rooms by default.
+pub const DEFAULT_REAPER_INTERVAL: Duration = Duration::from_secs(10);
 
-                /// send the text message if the given condition in it's closure is true.
-        pub async fn send_if<F>(&mut self, message: T, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            if condition(&self) { 
-                let _ = self.receiver.send(Message::Item(message)).await;
-            } 
-        }
+/// errors of the liblary. The ids they hold are the ids of the rooms or connections which the error belongs to.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// there is no room with that id.
+    UnknownRoom(String),
+    /// there is no connection with that id.
+    UnknownConnection(String),
+    /// a connection with that id already exists in the room.
+    DuplicateConnection(String),
+    /// a room with that id already exists.
+    DuplicateRoom(String),
+    /// writing to the socket of the connection is failed.
+    Send(String, Arc<dyn std::error::Error + Send + Sync>),
+    /// the outbound queue of the connection is full, so the message is not taken.
+    QueueFull(String),
+    /// the sink of the connection is closed, it can't take any message anymore.
+    Closed(String),
+    /// the connection couldn't finish the operation before the timeout.
+    Timeout(String),
+    /// the room with that id is at it's capacity.
+    RoomFull(String),
+    /// the connection with that id is refused by the admission check of the broadcaster.
+    Refused(String),
+    /// the broadcaster has too many connections, in total if there's no key or with the given key.
+    QuotaExceeded(Option<String>)
+}
 
-        /// sen the text message if the given condition in it's closure is true.
-        pub async fn send_if_not<F>(&mut self, message: T, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            if !condition(&self) { 
-                let _ = self.receiver.send(Message::Item(message)).await;
-            } 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownRoom(id) => write!(f, "there is no room with id \"{}\"", id),
+            Error::UnknownConnection(id) => write!(f, "there is no connection with id \"{}\"", id),
+            Error::DuplicateConnection(id) => write!(f, "a connection with id \"{}\" already exists", id),
+            Error::DuplicateRoom(id) => write!(f, "a room with id \"{}\" already exists", id),
+            Error::Send(id, error) => write!(f, "sending to connection \"{}\" failed: {}", id, error),
+            Error::QueueFull(id) => write!(f, "outbound queue of connection \"{}\" is full", id),
+            Error::Closed(id) => write!(f, "connection \"{}\" is closed", id),
+            Error::Timeout(id) => write!(f, "connection \"{}\" timed out", id),
+            Error::RoomFull(id) => write!(f, "room \"{}\" is full", id),
+            Error::Refused(id) => write!(f, "connection \"{}\" is refused", id),
+            Error::QuotaExceeded(None) => write!(f, "there are too many connections"),
+            Error::QuotaExceeded(Some(key)) => write!(f, "there are too many connections with key \"{}\"", key)
         }
+    }
+}
 
-        /// send the text message.
-        pub async fn ping(&mut self, message: &Vec<u8>) where T: Clone {
-            let _ = self.receiver.send(Message::Ping(message.clone())).await;
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Send(_, error) => Some(error.as_ref()),
+            _ => None
         }
+    }
+}
 
-        /// send the text message if the given condition in it's closure is true.
-        pub async fn ping_if<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            if condition(&self) { 
-                let _ = self.receiver.send(Message::Ping(message.clone())).await;
-            } 
-        }
+/// result type of the liblary.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-        /// sen the text message if the given condition in it's closure is true.
-        pub async fn ping_if_not<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            if !condition(&self) { 
-                let _ = self.receiver.send(Message::Ping(message.clone())).await;
-            } 
-        }
+/// result of the closing methods, tells which connections are closed cleanly. A connection is closed cleanly when it's pending messages and the close frame are flushed to the socket before the close timeout.
+#[derive(Debug, Clone, Default)]
+pub struct CloseReport {
+    pub clean: Vec<String>,
+    pub unclean: Vec<(String, Error)>
+}
 
-        /// send the text message.
-        pub async fn pong(&mut self, message: &Vec<u8>) where T: Clone {
-            let _ = self.receiver.send(Message::Pong(message.clone())).await;
-        }
+impl CloseReport {
+    /// returns true if every closed connection is closed cleanly.
+    pub fn is_clean(&self) -> bool {
+        self.unclean.is_empty()
+    }
+}
+
+/// result of the broadcasting methods, tells what happened to the message for each connection of the room.
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryReport {
+    /// connections which took the message into their outbound queue. Their writer tasks write it to the socket in order.
+    pub delivered: Vec<String>,
+    /// connections which couldn't take the message, with the reason.
+    pub failed: Vec<(String, Error)>,
+    /// connections which are skipped because of the condition of the method.
+    pub skipped: Vec<String>,
+    /// connections whose queue was full, so the message is dropped because of the backpressure policy.
+    pub dropped: Vec<String>,
+    /// sequence number of the message, if it's broadcasted to the whole room.
+    pub sequence: Option<u64>
+}
+
+impl DeliveryReport {
+    /// returns true if no connection is failed or dropped the message. Skipped connections don't count as failure.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.dropped.is_empty()
+    }
 
-                /// send the text message if the given condition in it's closure is true.
-        pub async fn pong_if<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            if condition(&self) { 
-                let _ = self.receiver.send(Message::Pong(message.clone())).await;
-            } 
+    /// the first failure of the report as an error, a dropped message is `Error::QueueFull`.
+    pub(crate) fn into_result(self) -> Result<()> {
+        match (self.failed.into_iter().next(), self.dropped.into_iter().next()) {
+            (Some((_, error)), _) => Err(error),
+            (None, Some(id)) => Err(Error::QueueFull(id)),
+            (None, None) => Ok(())
         }
+    }
+}
 
-        /// sen the text message if the given condition in it's closure is true.
-        pub async fn pong_if_not<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            if !condition(&self) { 
-                let _ = self.receiver.send(Message::Pong(message.clone())).await;
-            } 
+/// state of a room, which is kept as long as the room lives. Put anything else your application wants to keep in the room into `data`.
+#[derive(Debug, Clone)]
+pub struct RoomState<D = ()> {
+    pub title: Option<String>,
+    pub owner: Option<String>,
+    pub topic: Option<String>,
+    pub created_at: SystemTime,
+    /// how many connections the room takes at most, connections which share an id are counted one by one. `None` is unlimited.
+    pub capacity: Option<usize>,
+    /// when the reaper closes the room, such as the end of a webinar. `None` keeps it open.
+    pub expires_at: Option<SystemTime>,
+    pub data: D
+}
+
+impl<D> RoomState<D> {
+    /// create the state of a room which is created now, with given data.
+    pub fn new(data: D) -> Self {
+        Self {
+            title: None,
+            owner: None,
+            topic: None,
+            created_at: SystemTime::now(),
+            capacity: None,
+            expires_at: None,
+            data
         }
     }
+}
 
-    impl<T: Display + Serialize, S: Display + Serialize> Room<T, S> where SplitSink<WebSocket<T, S>, Message<T>>: Sink<Message<T>> + Unpin {
-        /// check if a connection with given id exist and if it's not, add a connection to a room with that ip:
-        pub fn add_connection(&mut self, id: &String, receiver: SplitSink<WebSocket<T, S>, Message<T>>) {
-            let check_is_connection_exist = self.connections.iter().any(|room| room.id == *id);
+impl<D: Default> Default for RoomState<D> {
+    fn default() -> Self {
+        Self::new(D::default())
+    }
+}
 
-            match check_is_connection_exist {
-                true => (),
-                false => {
-                    let connection = Connection {
-                        id: id.clone(),
-                        receiver
-                    };
+/// settings of the message history of rooms. Every room keeps the messages which are broadcasted to all of it's connections, the last `replay`
+/// of them are sent to each connection which joins the room, before anything else.
+#[derive(Debug, Clone)]
+pub struct History {
+    /// how many messages each room keeps at most.
+    pub capacity: usize,
+    /// how long a message is kept, `None` keeps it until the newer ones push it out.
+    pub max_age: Option<Duration>,
+    /// how many of the last messages are sent to a connection when it joins a room. Zero means they're only sent on demand, with `Room::replay()`.
+    pub replay: usize
+}
 
-                    self.connections.push(connection);
-                }
-            }
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            capacity: 100,
+            max_age: None,
+            replay: 0
         }
+    }
+}
 
-        /// remove a connection from room:
-        pub fn remove_connection(&mut self, id: String) {
-            self.connections.retain(|connection| { 
-                if connection.id == id { 
-                    false 
-                } else { 
-                    true 
-                }
-            });
-        }
+/// what `Broadcaster::handle()` does when the room is at it's capacity. Connections which join a room in other ways, such as with `Room::join()`, are always rejected.
+#[derive(Debug, Clone, Default)]
+pub enum CapacityPolicy {
+    /// fail with `Error::RoomFull`. It's the default.
+    #[default]
+    Reject,
+    /// wait on the waitlist of the room at most for given duration and join when a seat frees up, in the order of coming. It fails with `Error::RoomFull` if no seat frees up in time.
+    Waitlist(Duration)
+}
 
-        /// check if a connection exist and return if it's.
-        pub fn check_connection(&mut self, id: &String) -> Option<&Connection<T, S>> {
-            let connection = self.connections.iter().find(|room| room.id == *id);
+/// what happened when a connection resumed from the last sequence number it has seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// the messages which are broadcasted after the last seen one are replayed, it's how many of them.
+    Replayed(usize),
+    /// the history doesn't go back far enough, so nothing is replayed and the client should load everything again. `oldest` is the sequence number of the oldest message which is kept.
+    GapTooLarge { last_seen: u64, oldest: u64 },
+    /// the missed messages don't fit into the outbound queue of the connection, which has `free` places, so nothing is replayed and the client should load everything again.
+    TooManyMissed { missed: usize, free: usize }
+}
 
-            match connection {
-                Some(connection) => Some(connection),
-                None => None
-            }
-        }
+/// what happened to a member of a room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresenceKind {
+    Joined,
+    Left,
+    /// the metadata of the member is changed.
+    Updated
+}
 
-        /// broadcast the message directly:
-        pub async fn broadcast(&mut self, message: &T) where T: Clone { 
-            for connection in &mut self.connections { 
-                let msg = Message::Item(message.clone());
-                let receiver = &mut connection.receiver; 
-                
-                let _ = receiver.send(msg).await;
-            }
-        }
+/// a change in the members of a room, which is given to the presence format of the broadcaster for announcing it to the other members.
+#[derive(Debug)]
+pub struct PresenceEvent<'a, M> {
+    pub kind: PresenceKind,
+    pub room: &'a str,
+    pub id: &'a str,
+    pub meta: &'a M
+}
 
-        /// broadcast the message if the given condition in it's closure is true.
-        pub async fn broadcast_if<F>(&mut self, message: &T, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            for connection in &mut self.connections { 
-                if condition(connection) { 
-                    let msg = Message::Item(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
+/// tells which connection is evicted from which room and why it's evicted.
+#[derive(Debug, Clone)]
+pub struct Eviction {
+    pub room: String,
+    pub connection: String,
+    pub error: Error
+}
 
-        /// broadcast the message if the given condition in it's closure is false.
-        pub async fn broadcast_if_not<F>(&mut self, message: &T, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            for connection in &mut self.connections { 
-                if !condition(connection) { 
-                    let msg = Message::Item(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
+/// callback which is called after a connection is evicted from it's room because of a failed send.
+pub type EvictHook = Arc<dyn Fn(&Eviction) + Send + Sync>;
 
-        /// broadcast the message directly:
-        pub async fn ping(&mut self, message: &Vec<u8>) where T: Clone {
-            for connection in &mut self.connections { 
-                let msg = Message::Ping(message.clone());
-                let receiver = &mut connection.receiver; 
-                        
-                let _ = receiver.send(msg).await;
-            }
-        }
+/// something which happened in a broadcaster, for the `on_event` callback of the config.
+#[derive(Debug, Clone)]
+pub enum Event {
+    RoomCreated { room: String },
+    RoomRemoved { room: String },
+    /// the last connection of the room is left, the room itself still exists.
+    RoomEmptied { room: String },
+    Joined { room: String, connection: String },
+    Left { room: String, connection: String },
+    /// a message couldn't be put into the queue of the connection, or writing to it's socket is failed.
+    SendFailed { room: String, connection: String, error: Error }
+}
 
-        /// broadcast the message if the given condition in it's closure is true.
-        pub async fn ping_if<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            for connection in &mut self.connections { 
-                if condition(connection) { 
-                    let msg = Message::Ping(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
-        
-        /// broadcast the message if the given condition in it's closure is false.
-        pub async fn ping_if_not<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            for connection in &mut self.connections { 
-                if !condition(connection) { 
-                    let msg = Message::Ping(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
+/// callback which is called with every event of a broadcaster.
+pub type EventHook = Arc<dyn Fn(&Event) + Send + Sync>;
 
-        /// broadcast the pong message directly:
-        pub async fn pong(&mut self, message: &Vec<u8>) where T: Clone {
-            for connection in &mut self.connections { 
-                let msg = Message::Pong(message.clone());
-                let receiver = &mut connection.receiver; 
-                                
-                let _ = receiver.send(msg).await;
-            }
-        }
-        
-        /// broadcast the pong message if the given condition in it's closure is true.
-        pub async fn pong_if<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            for connection in &mut self.connections { 
-                if condition(connection) { 
-                    let msg = Message::Pong(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
-                
-        /// broadcast the pong message if the given condition in it's closure is false.
-        pub async fn pong_if_not<F>(&mut self, message: &Vec<u8>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            for connection in &mut self.connections { 
-                if !condition(connection) { 
-                    let msg = Message::Pong(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
-
-        /// it's most convenient way to close a single connection but keeping room open.
-        pub async fn close_conn(&mut self, close_frame: Option<CloseFrame<'static>>, id: &String) where T: Clone {
-            self.connections.retain_mut(|connection| {
-                if connection.id == *id {
-                    let msg = Message::Close(close_frame.clone());
-                    let receiver = &mut connection.receiver; 
-                                                    
-                    let _ = async {
-                        let _ = receiver.send(msg).await;
-                    };
-                    
-                    false
-                } else {
-                    true
-                }
-            });
-        }
+/// call the event callback with the event which is built by the closure, if there is a callback.
+pub(crate) fn emit<F>(hook: &Option<EventHook>, event: F) where F: FnOnce() -> Event {
+    if let Some(hook) = hook {
+        hook(&event());
+    }
+}
 
-        /// Close all connections and remove it from it's room but not close it.
-        pub async fn close(&mut self, close_frame: Option<CloseFrame<'static>>) where T: Clone { 
-            self.connections.retain_mut(|connection| {
-                let msg = Message::Close(close_frame.clone());
-                let receiver = &mut connection.receiver; 
-                                                        
-                let _ = async {
-                    let _ = receiver.send(msg).await;
-                };
-                        
-                false
-            });
-        }
-        
-        /// close each connection and remove them from room if the given condition in it's closure is true.
-        pub async fn close_if<F>(&mut self, close_frame: Option<CloseFrame<'static>>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            self.connections.retain_mut(|connection| {
-                if condition(&connection) {
-                    let msg = Message::Close(close_frame.clone());
-                    let receiver = &mut connection.receiver; 
-                                                            
-                    let _ = async {
-                        let _ =  receiver.send(msg).await;
-                    };
-                            
-                    false
-                } else {
-                    true
-                }
-            });
-        }
-        
-        /// close each connection and remove them from room if the given condition in it's closure is false.
-        pub async fn close_if_not<F>(&mut self, close_frame: Option<CloseFrame<'static>>, condition: F) where F: Fn(&Connection<T, S>) -> bool, T: Clone { 
-            self.connections.retain_mut(|connection| {
-                if !condition(&connection) {
-                    let msg = Message::Close(close_frame.clone());
-                    let receiver = &mut connection.receiver; 
-                                                                    
-                    let _ = async {
-                        let _ =  receiver.send(msg).await;
-                    };
-                                    
-                    false
-                } else {
-                    true
-                }
-            });
-        }
+/// settings which a broadcaster shares with all of it's rooms, so a change on them is visible everywhere immediately.
+#[derive(Debug, Default)]
+pub(crate) struct Shared<C>(Arc<std::sync::RwLock<C>>);
+
+impl<C: Clone> Shared<C> {
+    pub(crate) fn new(value: C) -> Self {
+        Self(Arc::new(std::sync::RwLock::new(value)))
     }
 
-    impl<T: Display + Serialize, S: Display + Serialize> Broadcaster<T, S> {
-        /// create new broadcaster:
-        pub fn new() -> Arc<RwLock<Self>> {
-            Arc::new(RwLock::new(Self::default()))
-        }
+    pub(crate) fn get(&self) -> C {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
 
-        /// get receiver and stream, similar to ".handle()" method of actix-ws.
-        pub fn configure(socket: WebSocket<T, S>) -> (SplitSink<WebSocket<T, S>, Message<T>>, SplitStream<WebSocket<T, S>>) where WebSocket<T, S>: Sink<Message<T>> + Stream + Sized {
-            socket.split()
-        }
+    pub(crate) fn set(&self, value: C) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = value;
+    }
+}
 
-        /// handle the all thing. If you use that api, there is no need to any other configuration for grouping and identifying connections:
-        pub async fn handle(broadcaster: &Arc<RwLock<Self>>, room_id: &String, conn_id: &String, receiver: SplitSink<WebSocket<T, S>, Message<T>>) -> Arc<RwLock<Self>> {
-            let mut broadcaster_write = broadcaster.write().await;
+impl<C> Clone for Shared<C> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
 
-            broadcaster_write.handle_room(room_id).add_connection(conn_id, receiver);
+/// tells which rooms each connection id is in. Rooms keep it up to date while they add and remove connections, so a broadcaster can find a connection without walking through every room.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Index(Arc<Mutex<HashMap<String, HashSet<String>>>>);
 
-            Arc::clone(&broadcaster)
-        }
+impl Index {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, HashSet<String>>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
 
-        /// check if a room with given id exist and if it's not create one.
-        pub fn handle_room(&mut self, id: &String) -> &mut Room<T, S> {
-            if let Some(index) = self.rooms.iter().position(|room| room.id == *id) {
-                return &mut self.rooms[index];
-            }
-        
-            self.rooms.push(Room {
-                id: id.clone(),
-                connections: vec![],
-            });
-        
-            self.rooms.last_mut().unwrap()
-        }
+    pub(crate) fn insert(&self, connection: &str, room: &str) {
+        self.lock().entry(connection.to_string()).or_default().insert(room.to_string());
+    }
 
-        /// iterates through every room and does something with them immutably. You cannot mutate anything inside of it, even rooms and not captured variables.      
-        /// 
-        /// ```rust
-        /// 
-        /// use axum_wsb::typed::Broadcaster;
-        /// use tokio::sync::RwLock;
-        /// use std::sync::Arc;
-        /// 
-        /// fn main () {
-        ///     let receivers = Broadcaster::new();
-        /// 
-        ///     async {
-        ///         receivers.read().await.each_room_immut(|room| println!("hello, {}. guest!", room.id));
-        ///     };
-        /// 
-        /// }
-        /// 
-        /// 
-        /// ```
-        pub fn each_room_immut<F>(&self, f: F) where F: Fn(&Room<T, S>) {
-            for room in &self.rooms {
-                f(room);
-            }
-        }
+    pub(crate) fn remove(&self, connection: &str, room: &str) {
+        let mut index = self.lock();
 
-        /// iterates through every room and does something with them immutably. You cannot mutate rooms itself but can mutate captured variables.
-        /// 
-        /// ```rust
-        /// 
-        /// use axum_wsb::typed::Broadcaster;
-        /// use tokio::sync::RwLock;
-        /// use std::sync::Arc;
-        /// 
-        /// fn main () {
-        ///     let receivers = Broadcaster::new();
-        /// 
-        ///     let mut num = 0;
-        /// 
-        ///     async {
-        ///         receivers.read().await.each_room(|room| {
-        ///             num = num + 1;
-        ///         });
-        ///     };
-        /// 
-        /// 
-        ///     println!("here is number: {}", num)
-        /// }
-        /// 
-        /// 
-        /// ```
-        pub fn each_room<F>(&self, mut f: F) where F: FnMut(&Room<T, S>) {
-            for room in &self.rooms {
-                f(room);
+        if let Some(rooms) = index.get_mut(connection) {
+            rooms.remove(room);
+
+            if rooms.is_empty() {
+                index.remove(connection);
             }
         }
+    }
 
-        /// iterates through every room and does something with them mutably. You can mutate everything belong to it. But warning, for now, you cannot send messages to client from it right now and until async closures will be stable probably we're not be able to do it. Because of that, we're not able to give examples for that.
-        pub async fn each_room_mut<F>(&mut self, mut f: F) where F: FnMut(&mut Room<T, S>) {
-            for room in &mut self.rooms {
-                f(room);
-            }
+    pub(crate) fn rooms_of(&self, connection: &str) -> Vec<String> {
+        match self.lock().get(connection) {
+            Some(rooms) => rooms.iter().cloned().collect(),
+            None => vec![]
         }
+    }
+}
+
+/// counts the connections of a broadcaster, in total and by their quota keys.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Quotas(Arc<Mutex<Counts>>);
+
+#[derive(Debug, Default)]
+struct Counts {
+    total: usize,
+    keys: HashMap<String, usize>
+}
 
-        /// Get the Room with given id. If there is a risk of unextistance of the room, use ".check_room()" instead.
-        pub fn room(&mut self, id: &String) -> &mut Room<T, S> {
-            return self.rooms.iter_mut().find(|room| room.id == *id).unwrap();
+impl Quotas {
+    fn lock(&self) -> MutexGuard<'_, Counts> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// count a connection with given key if the limits let it, it's counted until the returned permit is dropped.
+    pub(crate) fn acquire(&self, key: Option<String>, total: Option<usize>, per_key: Option<usize>) -> Result<Permit> {
+        let mut counts = self.lock();
+
+        if total.is_some_and(|total| counts.total >= total) {
+            return Err(Error::QuotaExceeded(None));
         }
 
-        /// check if a room with given id exist and wrap it in an option.
-        pub fn check_room(&mut self, id: &String) -> Option<&mut Room<T, S>> {
-            match self.rooms.iter_mut().find(|room| room.id == *id) {
-                Some(room) => Some(room),
-                None => None
+        if let Some(key) = &key {
+            let count = counts.keys.get(key).copied().unwrap_or(0);
+
+            if per_key.is_some_and(|per_key| count >= per_key) {
+                return Err(Error::QuotaExceeded(Some(key.clone())));
             }
-        }
 
-        /// only check if a room is exist and return a bool.
-        pub fn check(&self, id: &String) -> bool {
-            return self.rooms.iter().any(|room| room.id == *id);
+            counts.keys.insert(key.clone(), count + 1);
         }
 
-        /// it removes a room with given id and closes all the connections inside of it.
-        pub async fn remove_room(&mut self, id: &String) where T: Clone {
-            self.rooms.retain_mut(|room| { 
-                if room.id == *id {
-                    let _ = async {
-                        let _ = room.close(None).await;
-                    };
-                    
-                    false 
-                } else { 
-                    true 
-                }
-            });
-        }
+        counts.total += 1;
 
-        /// it removes all empty rooms.
-        pub fn remove_empty_rooms(&mut self) {
-            self.rooms.retain(|room| { 
-                if room.connections.is_empty() { 
-                    false 
-                } else { 
-                    true 
-                }
-            });
-        }
+        Ok(Permit { quotas: self.clone(), key })
+    }
 
-        /// Removes the connection from Room. Warning: Because the async closures are not stable yet, we cannot close the connection in that function, you have to make cleanup on your cadebase. For that, check the examples & Documentation.
-        pub fn remove_connection(&mut self, id: &String) -> Option<SplitSink<WebSocket<T, S>, Message<T>>> {
-            for room in &mut self.rooms {
-                if let Some(pos) = room.connections.iter().position(|connection| connection.id == *id) {
-                    let connection = room.connections.remove(pos);
-                    return Some(connection.receiver);
+    pub(crate) fn total(&self) -> usize {
+        self.lock().total
+    }
+
+    pub(crate) fn count(&self, key: &str) -> usize {
+        self.lock().keys.get(key).copied().unwrap_or(0)
+    }
+}
+
+/// a counted connection, it's given back to the quotas when it's dropped.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    quotas: Quotas,
+    key: Option<String>
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut counts = self.quotas.lock();
+
+        counts.total -= 1;
+
+        if let Some(key) = &self.key {
+            if let Some(count) = counts.keys.get_mut(key) {
+                *count -= 1;
+
+                if *count == 0 {
+                    counts.keys.remove(key);
                 }
             }
-            None
         }
     }
+}
+
+/// the token bucket which limits the inbound messages of a connection.
+#[derive(Debug)]
+pub(crate) struct Bucket {
+    tokens: f64,
+    updated: Instant
+}
+
+impl Bucket {
+    /// a full bucket, it's capped to the burst on the first take.
+    pub(crate) fn new() -> Self {
+        Self { tokens: f64::MAX, updated: Instant::now() }
+    }
 
-    impl<T, S> Default for Broadcaster<T, S> { 
-        fn default() -> Self { 
-            Self { 
-                rooms: vec![], 
-            } 
+    /// take a token after refilling the bucket by the time which passed, `false` is returned if it's empty.
+    pub(crate) fn take(&mut self, per_second: f64, burst: u32) -> bool {
+        let now = Instant::now();
+
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * per_second).min(burst as f64);
+        self.updated = now;
+
+        match self.tokens >= 1.0 {
+            true => {
+                self.tokens -= 1.0;
+
+                true
+            },
+            false => false
         }
     }
 }
 
-pub mod normal {
-    use axum_8_4::{body::Bytes, extract::ws::{CloseFrame, Message, Utf8Bytes, WebSocket}};
-    use std::sync::Arc;
-    use tokio::sync::RwLock;
-    use futures_util::{sink::SinkExt, stream::{SplitSink, SplitStream, StreamExt}};
+/// what the heartbeat knows about a connection. It's shared by the clones of the connection, so a connection which is in many rooms is pinged once.
+#[derive(Debug, Default)]
+pub(crate) struct Liveness(Mutex<Beat>);
 
-    /// main broadcaster for normal api.
-    #[derive(Debug)]
-    pub struct Broadcaster {
-        pub rooms: Vec<Room>
-    }
+#[derive(Debug, Default)]
+struct Beat {
+    sequence: u64,
+    pending: Option<(u64, Instant)>,
+    last_pong: Option<Instant>,
+    latency: Option<Duration>,
+    missed: u32
+}
 
-    /// room implementation.
-    #[derive(Debug)]
-    pub struct Room {
-        pub id: String,
-        pub connections: Vec<Connection>
+impl Liveness {
+    fn lock(&self) -> MutexGuard<'_, Beat> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    /// type for each individual connection.
-    #[derive(Debug)]
-    pub struct Connection {
-        pub id: String,
-        pub receiver: SplitSink<WebSocket, Message>
-    }
+    /// count the previous ping as missed if it's not answered yet, than give how many pings are missed in a row and the payload of the next ping.
+    pub(crate) fn ping(&self) -> (u32, [u8; 8]) {
+        let mut beat = self.lock();
 
-    impl Connection {
-        /// create a connection:
-        pub fn create(id: String, receiver: SplitSink<WebSocket, Message>) -> Self {
-            Self {
-                id, 
-                receiver
-            }
+        if beat.pending.is_some() {
+            beat.missed += 1;
         }
 
-        // send the text message.
-        pub async fn send(&mut self, message: Utf8Bytes) -> Result<(), axum_8_4::Error> {
-            match self.receiver.send(Message::Text(message)).await {
-                Ok(_) => Ok(()),
-                Err(error) => Err(error)
-            }
-        }
+        beat.sequence += 1;
+        beat.pending = Some((beat.sequence, Instant::now()));
 
-        /// send the text message if the given condition in it's closure is true.
-        pub async fn send_if<F>(&mut self, message: Utf8Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            if condition(&self) { 
-                let _ = self.receiver.send(Message::Text(message)).await;
-            } 
-        }
+        (beat.missed, beat.sequence.to_be_bytes())
+    }
 
-        /// sen the text message if the given condition in it's closure is true.
-        pub async fn send_if_not<F>(&mut self, message: Utf8Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            if !condition(&self) { 
-                let _ = self.receiver.send(Message::Text(message)).await;
-            } 
-        }
+    /// any pong shows that the client is alive, but the latency is only measured with the pong of the last ping.
+    pub(crate) fn pong(&self, payload: &[u8]) {
+        let mut beat = self.lock();
+        let now = Instant::now();
 
-        /// send the ping.
-        pub async fn ping(&mut self, message: Bytes) -> Result<(), axum_8_4::Error> {
-            match self.receiver.send(Message::Ping(message)).await {
-                Ok(_) => Ok(()),
-                Err(error) => Err(error)
+        if let Some((sequence, sent)) = beat.pending.take() {
+            if payload == sequence.to_be_bytes() {
+                beat.latency = Some(now - sent);
             }
         }
 
-        /// send the ping if the given condition in it's closure is true.
-        pub async fn ping_if<F>(&mut self, message: Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            if condition(&self) { 
-                let _ = self.receiver.send(Message::Ping(message)).await;
-            } 
-        }
+        beat.last_pong = Some(now);
+        beat.missed = 0;
+    }
 
-        /// sen the ping if the given condition in it's closure is true.
-        pub async fn ping_if_not<F>(&mut self, message: Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            if !condition(&self) { 
-                let _ = self.receiver.send(Message::Ping(message)).await;
-            } 
-        }
+    pub(crate) fn last_pong(&self) -> Option<Instant> {
+        self.lock().last_pong
+    }
 
-        /// send the pong.
-        pub async fn pong(&mut self, message: Bytes) -> Result<(), axum_8_4::Error> {
-            match self.receiver.send(Message::Pong(message)).await {
-                Ok(_) => Ok(()),
-                Err(error) => Err(error)
-            }
-        }
+    pub(crate) fn latency(&self) -> Option<Duration> {
+        self.lock().latency
+    }
 
-        /// send the pong if the given condition in it's closure is true.
-        pub async fn pong_if<F>(&mut self, message: Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            if condition(&self) { 
-                let _ = self.receiver.send(Message::Pong(message)).await;
-            } 
-        }
+    pub(crate) fn missed(&self) -> u32 {
+        self.lock().missed
+    }
+}
 
-        /// sen the pong if the given condition in it's closure is true.
-        pub async fn pong_if_not<F>(&mut self, message: Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            if !condition(&self) { 
-                let _ = self.receiver.send(Message::Pong(message)).await;
-            } 
-        }
+/// the sequence numbers and the messages a room keeps for replaying them. Messages are only recorded by the methods which can clone them, so
+/// the function which clones them is kept with them and replaying doesn't need any bound.
+pub(crate) struct Backlog<M>(Mutex<Recorded<M>>);
+
+struct Recorded<M> {
+    messages: VecDeque<(u64, Instant, M)>,
+    sequence: u64,
+    clone: Option<fn(&M) -> M>
+}
+
+impl<M> Backlog<M> {
+    fn lock(&self) -> MutexGuard<'_, Recorded<M>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    impl Room {
-        /// check if a connection with given id exist and if it's not, add a connection to a room with that ip:
-        pub fn add_connection(&mut self, id: &String, receiver: SplitSink<WebSocket, Message>) {
-            let check_is_connection_exist = self.connections.iter().any(|room| room.id == *id);
+    /// give the next sequence number to the message which is built by the closure and keep it if the history is on, forgetting the ones which don't fit into it anymore.
+    pub(crate) fn record<F>(&self, message: F, history: Option<&History>) -> (u64, M) where F: FnOnce(u64) -> M, M: Clone {
+        let mut recorded = self.lock();
 
-            match check_is_connection_exist {
-                true => (),
-                false => {
-                    let connection = Connection {
-                        id: id.clone(),
-                        receiver
-                    };
+        recorded.sequence += 1;
 
-                    self.connections.push(connection);
-                }
+        let sequence = recorded.sequence;
+        let message = message(sequence);
+
+        if let Some(history) = history {
+            recorded.clone = Some(M::clone);
+            recorded.messages.push_back((sequence, Instant::now(), message.clone()));
+
+            while recorded.messages.len() > history.capacity {
+                recorded.messages.pop_front();
             }
-        }
 
-        /// remove a connection from room with given id.
-        pub fn remove_connection(&mut self, id: String) {
-            self.connections.retain(|connection| { 
-                if connection.id == id { 
-                    false 
-                } else { 
-                    true 
-                }
-            });
+            recorded.expire(history.max_age);
         }
 
-        /// check if a connection exist and return if it's in an option.
-        pub fn check_connection(&mut self, id: &String) -> Option<&Connection> {
-            let connection = self.connections.iter().find(|room| room.id == *id);
+        (sequence, message)
+    }
 
-            match connection {
-                Some(connection) => Some(connection),
-                None => None
-            }
-        }
+    /// sequence number of the last recorded message.
+    pub(crate) fn sequence(&self) -> u64 {
+        self.lock().sequence
+    }
 
-        /// Broadcast the message directly.
-        pub async fn broadcast(&mut self, message: &Utf8Bytes) { 
-            for connection in &mut self.connections { 
-                let msg = Message::Text(message.clone());
-                let receiver = &mut connection.receiver; 
-                
-                let _ = receiver.send(msg).await;
-            }
-        }
+    /// the messages which are recorded after the given sequence number, oldest first. If some of them are not kept anymore, the sequence number of the oldest kept message is given instead.
+    pub(crate) fn since(&self, last_seen: u64, max_age: Option<Duration>) -> std::result::Result<Vec<M>, u64> {
+        let mut recorded = self.lock();
 
-        /// broadcast the message if the given condition in it's closure is true.
-        pub async fn broadcast_if<F>(&mut self, message: &Utf8Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            for connection in &mut self.connections { 
-                if condition(connection) { 
-                    let msg = Message::Text(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
+        recorded.expire(max_age);
+
+        let oldest = recorded.messages.front().map_or(recorded.sequence + 1, |(sequence, _, _)| *sequence);
+
+        // a sequence number from the future belongs to an older room with the same id.
+        if last_seen > recorded.sequence || last_seen + 1 < oldest {
+            return Err(oldest);
         }
 
-        /// broadcast the message if the given condition in it's closure is false.
-        pub async fn broadcast_if_not<F>(&mut self, message: &Utf8Bytes, condition: F) where F: Fn(&Connection) -> bool { 
-            for connection in &mut self.connections { 
-                if !condition(connection) { 
-                    let msg = Message::Text(message.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
+        let Some(clone) = recorded.clone else {
+            return Ok(vec![]);
+        };
 
-        /// Broadcast the ping message directly.
-        pub async fn ping(&mut self, bytes: &Bytes) { 
-            for connection in &mut self.connections { 
-                let msg = Message::Ping(bytes.clone());
-                let receiver = &mut connection.receiver; 
-                        
-                let _ = receiver.send(msg).await;
-            }
-        }
-        
-        /// broadcast the ping message if the given condition in it's closure is true.
-        pub async fn ping_if<F>(&mut self, bytes: &Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            for connection in &mut self.connections { 
-                if condition(connection) { 
-                    let msg = Message::Ping(bytes.clone());
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
-        
-        /// broadcast the ping message if the given condition in it's closure is false.
-        pub async fn ping_if_not<F>(&mut self, bytes: &Bytes, condition: F) where F: Fn(&Connection) -> bool { 
-            for connection in &mut self.connections { 
-                if !condition(connection) { 
-                    let msg = Message::Ping(bytes.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
+        Ok(recorded.messages.iter().filter(|(sequence, _, _)| *sequence > last_seen).map(|(_, _, message)| clone(message)).collect())
+    }
+
+    /// the last `count` messages which are not older than the max age, oldest first.
+    pub(crate) fn recent(&self, count: usize, max_age: Option<Duration>) -> Vec<M> {
+        let mut recorded = self.lock();
+
+        recorded.expire(max_age);
+
+        let Some(clone) = recorded.clone else {
+            return vec![];
+        };
+
+        let skip = recorded.messages.len().saturating_sub(count);
+
+        recorded.messages.iter().skip(skip).map(|(_, _, message)| clone(message)).collect()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.lock().messages.clear();
+    }
+}
+
+impl<M> Recorded<M> {
+    fn expire(&mut self, max_age: Option<Duration>) {
+        let Some(max_age) = max_age else {
+            return;
+        };
+
+        while self.messages.front().is_some_and(|(_, recorded, _)| recorded.elapsed() > max_age) {
+            self.messages.pop_front();
         }
+    }
+}
 
-        /// Broadcast the pong message directly.
-        pub async fn pong(&mut self, bytes: &Bytes) { 
-            for connection in &mut self.connections { 
-                let msg = Message::Pong(bytes.clone());
-                let receiver = &mut connection.receiver; 
-                                
-                let _ = receiver.send(msg).await;
+impl<M> Default for Backlog<M> {
+    fn default() -> Self {
+        Self(Mutex::new(Recorded {
+            messages: VecDeque::new(),
+            sequence: 0,
+            clone: None
+        }))
+    }
+}
+
+impl<M> fmt::Debug for Backlog<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Backlog").field("len", &self.lock().messages.len()).finish()
+    }
+}
+
+/// the outbound queue of a connection. It works like a bounded channel whose only receiver is the writer task, but a full queue can also give up
+/// it's pending messages, so the backpressure policies can choose what to drop. Clones share the same queue.
+pub(crate) struct Outbox<M>(Arc<Queue<M>>);
+
+struct Queue<M> {
+    pending: Mutex<Pending<M>>,
+    capacity: usize,
+    readable: Notify,
+    writable: Notify
+}
+
+struct Pending<M> {
+    messages: VecDeque<M>,
+    /// messages which wait for a place in the queue with their tickets, in the order they came. They're moved into it as soon as the writer takes the pending ones.
+    waiting: VecDeque<(u64, M)>,
+    tickets: u64,
+    senders: usize,
+    closed: bool,
+    dropped: u64
+}
+
+/// what happened to a message which is put into an outbound queue without waiting.
+pub(crate) enum Queued {
+    /// it's queued, or dropped by the backpressure policy if it's `false`.
+    Taken(bool),
+    /// it waits for a place in the queue with given ticket, see `Outbox::wait_turn()`.
+    Waiting(u64)
+}
+
+/// what `Outbox::offer()` does when the queue is full.
+pub(crate) enum Overflow<M> {
+    /// fail with `Error::QueueFull`.
+    Reject,
+    DropOldest,
+    DropNewest,
+    /// drop the pending messages which satisfy the condition, or the oldest one if none of them does.
+    Coalesce(fn(&M) -> bool),
+    /// drop every pending message, queue the given close message and don't take anything else.
+    Close(M)
+}
+
+impl<M> Queue<M> {
+    fn lock(&self) -> MutexGuard<'_, Pending<M>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// take everything that piled up in the queue, waiting until there is something. `None` means nothing will come anymore, because the queue is closed or every sender is dropped.
+    async fn next(&self) -> Option<Vec<M>> {
+        loop {
+            {
+                let mut pending = self.lock();
+
+                if !pending.messages.is_empty() {
+                    let batch = pending.messages.drain(..).collect();
+                    let Pending { messages, waiting, .. } = &mut *pending;
+
+                    // the messages which wait for a place take it in their order, so nothing which came after them can pass them.
+                    messages.extend(waiting.drain(..waiting.len().min(self.capacity)).map(|(_, message)| message));
+
+                    drop(pending);
+                    self.writable.notify_waiters();
+
+                    return Some(batch);
+                }
+
+                if pending.closed || pending.senders == 0 {
+                    return None;
+                }
             }
+
+            self.readable.notified().await;
         }
-                
-        /// broadcast the pong message if the given condition in it's closure is true.
-        pub async fn pong_if<F>(&mut self, bytes: &Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            for connection in &mut self.connections { 
-                if condition(connection) { 
-                    let msg = Message::Pong(bytes.clone());
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
-                
-        /// broadcast the pong message if the given condition in it's closure is false.
-        pub async fn pong_if_not<F>(&mut self, bytes: &Bytes, condition: F) where F: Fn(&Connection) -> bool { 
-            for connection in &mut self.connections { 
-                if !condition(connection) { 
-                    let msg = Message::Pong(bytes.clone()); 
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
+    }
+
+    /// stop taking messages and wake up the senders which wait for a place.
+    fn close(&self) {
+        self.lock().closed = true;
+        self.writable.notify_waiters();
+    }
+}
+
+impl<M> Outbox<M> {
+    fn new(capacity: usize) -> Self {
+        Self(Arc::new(Queue {
+            pending: Mutex::new(Pending {
+                messages: VecDeque::new(),
+                waiting: VecDeque::new(),
+                tickets: 0,
+                senders: 1,
+                closed: false,
+                dropped: 0
+            }),
+            capacity: capacity.max(1),
+            readable: Notify::new(),
+            writable: Notify::new()
+        }))
+    }
+
+    /// put the message into the queue without waiting. If the queue is full, the overflow rule decides what's dropped, `false` is returned if it's the given message.
+    pub(crate) fn offer(&self, id: &str, message: M, overflow: Overflow<M>) -> Result<bool> {
+        let mut pending = self.0.lock();
+
+        if pending.closed {
+            return Err(Error::Closed(id.to_string()));
         }
 
-        /// Broadcast the raw binary bytes directly.
-        pub async fn binary(&mut self, bytes: &Bytes) { 
-            for connection in &mut self.connections { 
-                let msg = Message::Binary(bytes.clone());
-                let receiver = &mut connection.receiver; 
-                                        
-                let _ = receiver.send(msg).await;
+        if pending.messages.len() >= self.0.capacity {
+            match overflow {
+                Overflow::Reject => return Err(Error::QueueFull(id.to_string())),
+                Overflow::DropOldest => {
+                    pending.messages.pop_front();
+                    pending.dropped += 1;
+                },
+                Overflow::DropNewest => {
+                    pending.dropped += 1;
+
+                    return Ok(false);
+                },
+                Overflow::Coalesce(droppable) => {
+                    let before = pending.messages.len();
+
+                    pending.messages.retain(|message| !droppable(message));
+
+                    if pending.messages.len() == before {
+                        pending.messages.pop_front();
+                    }
+
+                    pending.dropped += (before - pending.messages.len()) as u64;
+                },
+                Overflow::Close(close) => {
+                    pending.dropped += (pending.messages.len() + pending.waiting.len()) as u64 + 1;
+                    pending.messages.clear();
+                    pending.waiting.clear();
+                    pending.messages.push_back(close);
+                    pending.closed = true;
+
+                    drop(pending);
+                    self.0.readable.notify_one();
+
+                    return Err(Error::QueueFull(id.to_string()));
+                }
             }
         }
 
-        /// broadcast the raw binary bytes if the given condition in it's closure is true.
-        pub async fn binary_if<F>(&mut self, bytes: &Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            for connection in &mut self.connections { 
-                if condition(connection) { 
-                    let msg = Message::Binary(bytes.clone());
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
-        }
+        pending.messages.push_back(message);
 
-        /// broadcast the raw binary bytes if the given condition in it's closure is false.
-        pub async fn binary_if_not<F>(&mut self, bytes: &Bytes, condition: F) where F: Fn(&Connection) -> bool, { 
-            for connection in &mut self.connections { 
-                if !condition(connection) { 
-                    let msg = Message::Binary(bytes.clone());
-                    let receiver = &mut connection.receiver; 
-                    let _ = receiver.send(msg).await;
-                } 
-            } 
+        drop(pending);
+        self.0.readable.notify_one();
+
+        Ok(true)
+    }
+
+    /// put the message into the queue without waiting. If the queue is full, or other messages already wait for a place in it, the message waits behind them and the returned ticket is given to `.wait_turn()`.
+    pub(crate) fn reserve(&self, id: &str, message: M) -> Result<Option<u64>> {
+        let mut pending = self.0.lock();
+
+        if pending.closed {
+            return Err(Error::Closed(id.to_string()));
         }
 
-        /// Close all connections and remove it from it's room but not close it.
-        pub async fn close(&mut self, close_frame: Option<CloseFrame>) { 
-            self.connections.retain_mut(|connection| {
-                let msg = Message::Close(close_frame.clone());
-                let receiver = &mut connection.receiver; 
-                                                
-                let _ = async {
-                    let _ = receiver.send(msg).await;
-                };
-                
-                false
-            });
+        if pending.waiting.is_empty() && pending.messages.len() < self.0.capacity {
+            pending.messages.push_back(message);
+
+            drop(pending);
+            self.0.readable.notify_one();
+
+            return Ok(None);
         }
 
-        /// it's most convenient way to close a single connection but keeping room open.
-        pub async fn close_conn(&mut self, close_frame: Option<CloseFrame>, id: &String) {
-            self.connections.retain_mut(|connection| {
-                if connection.id == *id {
-                    let msg = Message::Close(close_frame.clone());
-                    let receiver = &mut connection.receiver; 
-                                                    
-                    let _ = async {
-                        let _ = receiver.send(msg).await;
-                    };
-                    
-                    false
-                } else {
-                    true
+        pending.tickets += 1;
+
+        let ticket = pending.tickets;
+
+        pending.waiting.push_back((ticket, message));
+
+        Ok(Some(ticket))
+    }
+
+    /// wait until the message with given ticket takes it's place in the queue, at most for the timeout. If it can't, it's taken back, so the ones behind it don't wait for it.
+    pub(crate) async fn wait_turn(&self, id: &str, ticket: u64, timeout: Duration) -> Result<()> {
+        let waiting = async {
+            loop {
+                let writable = self.0.writable.notified();
+
+                tokio::pin!(writable);
+
+                // start listening before looking at the queue, so a place which is opened in between is not missed.
+                writable.as_mut().enable();
+
+                {
+                    let pending = self.0.lock();
+
+                    if !pending.waiting.iter().any(|(waiting, _)| *waiting == ticket) {
+                        return Ok(());
+                    }
+
+                    if pending.closed {
+                        return Err(Error::Closed(id.to_string()));
+                    }
                 }
-            });
+
+                writable.await;
+            }
+        };
+
+        let result = tokio::time::timeout(timeout, waiting).await.unwrap_or_else(|_| Err(Error::Timeout(id.to_string())));
+
+        if result.is_err() {
+            let mut pending = self.0.lock();
+
+            match pending.waiting.iter().position(|(waiting, _)| *waiting == ticket) {
+                Some(position) => {
+                    pending.waiting.remove(position);
+                },
+                // it took it's place right before the timeout.
+                None => return Ok(())
+            }
         }
 
-        /// close each connection and remove them from room if the given condition in it's closure is true.
-        pub async fn close_if<F>(&mut self, close_frame: Option<CloseFrame>, condition: F) where F: Fn(&Connection) -> bool, { 
-            self.connections.retain_mut(|connection| {
-                if condition(&connection) {
-                    let msg = Message::Close(close_frame.clone());
-                    let receiver = &mut connection.receiver; 
-                                                    
-                    let _ = async {
-                        let _ =  receiver.send(msg).await;
-                    };
-                    
-                    false
-                } else {
-                    true
-                }
-            });
+        result
+    }
+
+    /// queue the close message behind the pending ones even if the queue is full and stop taking messages. It's `false` if the queue is already closed.
+    fn close_with(&self, close: M) -> bool {
+        let mut pending = self.0.lock();
+
+        if pending.closed {
+            return false;
         }
 
-        /// close each connection and remove them from room if the given condition in it's closure is false.
-        pub async fn close_if_not<F>(&mut self, close_frame: Option<CloseFrame>, condition: F) where F: Fn(&Connection) -> bool, { 
-            self.connections.retain_mut(|connection| {
-                if !condition(&connection) {
-                    let msg = Message::Close(close_frame.clone());
-                    let receiver = &mut connection.receiver; 
-                                                            
-                    let _ = async {
-                        let _ =  receiver.send(msg).await;
-                    };
-                            
-                    false
-                } else {
-                    true
+        let Pending { messages, waiting, .. } = &mut *pending;
+
+        messages.extend(waiting.drain(..).map(|(_, message)| message));
+        messages.push_back(close);
+        pending.closed = true;
+
+        drop(pending);
+        self.0.readable.notify_one();
+        self.0.writable.notify_waiters();
+
+        true
+    }
+
+    /// how many messages wait in the queue, or for a place in it.
+    pub(crate) fn queued(&self) -> usize {
+        let pending = self.0.lock();
+
+        pending.messages.len() + pending.waiting.len()
+    }
+
+    /// how many more messages the queue takes.
+    pub(crate) fn free(&self) -> usize {
+        self.0.capacity.saturating_sub(self.queued())
+    }
+
+    /// how many messages are dropped from the queue because of the backpressure policies.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.0.lock().dropped
+    }
+}
+
+impl<M> Clone for Outbox<M> {
+    fn clone(&self) -> Self {
+        self.0.lock().senders += 1;
+
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<M> Drop for Outbox<M> {
+    fn drop(&mut self) {
+        let mut pending = self.0.lock();
+
+        pending.senders -= 1;
+
+        if pending.senders == 0 {
+            drop(pending);
+            self.0.readable.notify_one();
+        }
+    }
+}
+
+impl<M> fmt::Debug for Outbox<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outbox")
+            .field("capacity", &self.0.capacity)
+            .field("queued", &self.queued())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// closes the queue when the writer stops for any reason, even when it's aborted, so nobody waits for a place in it anymore.
+struct Stopped<M>(Arc<Queue<M>>);
+
+impl<M> Drop for Stopped<M> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// spawn the task that owns the sink of a connection and writes everything pushed into the returned outbox, in order.
+/// Broadcasting methods only push into that queue, so a slow client cannot stall the room it's in. When the writer meets
+/// a close message, it writes it after the messages queued before it, closes the sink and stops. If writing to the sink
+/// fails, the writer stops and publishes the error on the returned watch channel. The watch channel is closed when the writer
+/// stops for any reason, so every clone of a connection can tell that it's socket is gone.
+pub(crate) fn spawn_writer<S, M>(id: String, mut sink: S, capacity: usize, is_close: fn(&M) -> bool) -> (Outbox<M>, AbortHandle, watch::Receiver<Option<Error>>) where S: Sink<M> + Unpin + Send + 'static, S::Error: std::error::Error + Send + Sync + 'static, M: Send + 'static {
+    let outbox = Outbox::new(capacity);
+    let queue = Stopped(Arc::clone(&outbox.0));
+    let (failed, failure) = watch::channel(None);
+
+    let writer = tokio::spawn(async move {
+        let written = async {
+            // write everything that piled up while we were busy, than flush them at once.
+            while let Some(batch) = queue.0.next().await {
+                for message in batch {
+                    if is_close(&message) {
+                        sink.send(message).await?;
+                        sink.close().await?;
+
+                        return Ok(());
+                    }
+
+                    sink.feed(message).await?;
                 }
-            });
+
+                sink.flush().await?;
+            }
+
+            Ok(())
+        };
+
+        let written: Result<(), S::Error> = written.await;
+
+        if let Err(error) = written {
+            failed.send_replace(Some(Error::Send(id, Arc::new(error))));
         }
+    });
+
+    (outbox, writer.abort_handle(), failure)
+}
+
+/// wait until the writer of a connection fails and give the error. If the writer stops without any error, `None` is returned.
+pub(crate) async fn writer_failure(mut failure: watch::Receiver<Option<Error>>) -> Option<Error> {
+    match failure.wait_for(Option::is_some).await {
+        Ok(error) => error.clone(),
+        Err(_) => None
     }
+}
 
-    impl Broadcaster {
-        /// create new broadcaster.
-        pub fn new() -> Arc<RwLock<Self>> {
-            Arc::new(RwLock::new(Self::default()))
+/// stop the writer if it doesn't finish before the timeout, such as when it's stuck on the socket of a client which doesn't read.
+pub(crate) async fn abort_after(writer: AbortHandle, failure: watch::Receiver<Option<Error>>, timeout: Duration) {
+    if tokio::time::timeout(timeout, writer_failure(failure)).await.is_err() {
+        writer.abort();
+    }
+}
+
+/// queue the close message behind the pending ones and wait until the writer flushes all of them. If it doesn't finish before the timeout, the writer is aborted and the sink is dropped.
+pub(crate) async fn shutdown_writer<M>(id: &str, outbox: Outbox<M>, writer: &AbortHandle, failure: watch::Receiver<Option<Error>>, close: M, timeout: Duration) -> Result<()> {
+    let closing = async {
+        // if the queue is already closed, the writer is finishing or finished and it's failure tells us why.
+        let queued = outbox.close_with(close);
+
+        drop(outbox);
+
+        match writer_failure(failure).await {
+            Some(error) => Err(error),
+            None if queued => Ok(()),
+            None => Err(Error::Closed(id.to_string()))
         }
+    };
 
-        /// get receiver and stream, similar to ".handle()" method of actix-ws.
-        pub fn configure(socket: WebSocket) -> (SplitSink<WebSocket, Message>, SplitStream<WebSocket>) {
-            socket.split()
+    match tokio::time::timeout(timeout, closing).await {
+        Ok(result) => result,
+        Err(_) => {
+            writer.abort();
+
+            Err(Error::Timeout(id.to_string()))
         }
+    }
+}
+
+#[cfg(feature = "typed")]
+pub mod typed {
+    use serde::{de::DeserializeOwned, Serialize};
+    use futures_util::{Sink, Stream, future::join_all, stream::{FuturesUnordered, SplitSink, SplitStream, StreamExt}};
+    use std::{collections::{HashMap, HashSet, VecDeque}, fmt::{self, Display}, future::Future, marker::PhantomData, ops::Deref, sync::{Arc, Mutex, OnceLock, PoisonError, Weak}, time::{Duration, SystemTime}};
+    use tokio::{sync::{oneshot, watch, RwLock}, task::AbortHandle, time::Instant};
+    use axum_typed_websockets::{Message, WebSocket};
+    use axum_7_9::extract::ws::CloseFrame;
+    use crate::{Backlog, Bucket, CapacityPolicy, CloseReport, DeliveryReport, Error, Event, EventHook, EvictHook, Eviction, History, Index, Liveness, Outbox, Overflow, Permit, PresenceEvent, PresenceKind, Queued, Quotas, Result, Resume, RoomState, Shared, DEFAULT_CLOSE_TIMEOUT, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_QUEUE_CAPACITY, DEFAULT_REAPER_INTERVAL, DEFAULT_SEND_TIMEOUT};
+
+    /// the sending half of a typed websocket.
+    pub type Receiver<T, S> = SplitSink<WebSocket<T, S>, Message<T>>;
+
+    /// main broadcaster for typed api. Rooms are kept by their ids and the ids of connections are indexed, so finding a room or a connection doesn't depend on how many of them exist.
+    #[derive(Debug)]
+    pub struct Broadcaster<T, S, M = (), D = ()> {
+        rooms: HashMap<String, RoomHandle<T, S, M, D>>,
+        index: Index,
+        config: Shared<Config>,
+        presence: Shared<Announcer<T, M>>,
+        admission: Shared<Gate<T, S, M, D>>,
+        quota_key: Shared<Keyer<T, S, M>>,
+        rate_limit: Shared<Option<RateLimit<T>>>,
+        quotas: Quotas,
+        heartbeat: Option<AbortHandle>,
+        reaper: Option<AbortHandle>
+    }
 
-        /// handle the all thing. If you use that api, there is no need to any other configuration for grouping and identifying connections:
-        pub async fn handle(broadcaster: &Arc<RwLock<Self>>, room_id: &String, conn_id: &String, receiver: SplitSink<WebSocket, Message>) -> Arc<RwLock<Self>> {
-            let mut broadcaster_write = broadcaster.write().await;
+    /// a room which can be locked by itself. Broadcaster only needs to be locked for getting it, so sending to a room never waits for the others.
+    pub type RoomHandle<T, S, M = (), D = ()> = Arc<RwLock<Room<T, S, M, D>>>;
 
-            broadcaster_write.handle_room(room_id).add_connection(conn_id, receiver);
+    /// a reference to a room which doesn't keep it alive.
+    type WeakRoom<T, S, M, D> = Weak<RwLock<Room<T, S, M, D>>>;
 
-            Arc::clone(&broadcaster)
-        }
+    /// a connection which is found by it's id, with the id of the room it's found in and a weak reference to that room.
+    type Target<T, S, M, D> = (String, WeakRoom<T, S, M, D>, Connection<T, S, M>);
+
+    /// a connection which a message is queued for, with the room it's in and what happened to the message.
+    type Queueing<'a, T, S, M, D> = (&'a WeakRoom<T, S, M, D>, &'a str, &'a Connection<T, S, M>, Result<Queued>);
 
-        /// check if a room with given id exist and if it's not create one:
-        pub fn handle_room(&mut self, id: &String) -> &mut Room {
-            if let Some(index) = self.rooms.iter().position(|room| room.id == *id) {
-                return &mut self.rooms[index];
+    /// connections which are evicted from a room, with the errors that evicted them.
+    type Evictions = Vec<(String, watch::Receiver<Option<Error>>, Error)>;
+
+    /// settings of a broadcaster. They are shared with every room of it.
+    #[derive(Clone)]
+    pub struct Config {
+        /// how long the closing methods wait for a connection to flush it's pending messages and the close frame.
+        pub close_timeout: Duration,
+        /// the close frame which is sent when the broadcaster closes connections by itself, such as in `Broadcaster::remove_room()`.
+        pub close_frame: Option<CloseFrame<'static>>,
+        /// how long broadcasting methods wait for a place in the outbound queue of each connection. Connections are waited concurrently, so a broadcast takes at most that long. Zero means not waiting at all.
+        pub send_timeout: Duration,
+        /// what broadcasting methods do when the outbound queue of a connection is full. Rooms can override it with `.set_backpressure()`.
+        pub backpressure: Backpressure,
+        /// what to do when a connection joins a room with an id which is already in there.
+        pub duplicate_policy: DuplicatePolicy,
+        /// what `.handle()` does when the room is at it's capacity.
+        pub capacity_policy: CapacityPolicy,
+        /// limits of the connections which the broadcaster takes. It's unlimited by default.
+        pub quota: Quota,
+        /// remove a connection from it's room as soon as writing to it's socket fails. It's off by default.
+        pub evict_on_error: bool,
+        /// remove a connection from it's room when a broadcast to it times out. It's off by default.
+        pub evict_on_timeout: bool,
+        /// called after a connection is evicted, if `evict_on_error` or `evict_on_timeout` is on.
+        pub on_evict: Option<EvictHook>,
+        /// called with every event of the broadcaster, such as rooms being created or connections leaving. Rooms or the broadcaster can be locked while it's called, so spawn a task if you need to lock them.
+        pub on_event: Option<EventHook>,
+        /// ping the connections in the background and close the ones which stop answering. It's off by default.
+        pub heartbeat: Option<Heartbeat>,
+        /// keep the recent broadcasts of each room and replay them to the connections which join later. It's off by default.
+        pub history: Option<History>,
+        /// remove the rooms which are left empty for too long and close the expired ones in the background. It's off by default.
+        pub reaper: Option<Reaper>
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                close_timeout: DEFAULT_CLOSE_TIMEOUT,
+                close_frame: None,
+                send_timeout: DEFAULT_SEND_TIMEOUT,
+                backpressure: Backpressure::Wait,
+                duplicate_policy: DuplicatePolicy::Reject,
+                capacity_policy: CapacityPolicy::Reject,
+                quota: Quota::default(),
+                evict_on_error: false,
+                evict_on_timeout: false,
+                on_evict: None,
+                on_event: None,
+                heartbeat: None,
+                history: None,
+                reaper: None
             }
-        
-            self.rooms.push(Room {
-                id: id.clone(),
-                connections: vec![],
-            });
-        
-            self.rooms.last_mut().unwrap()
         }
+    }
 
-        /// Get the Room with given id. If there is a risk of unextistance of the room, use ".check_room()" instead.
-        pub fn room(&mut self, id: &String) -> &mut Room {
-            return self.rooms.iter_mut().find(|room| room.id == *id).unwrap();
+    impl fmt::Debug for Config {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Config")
+                .field("close_timeout", &self.close_timeout)
+                .field("close_frame", &self.close_frame)
+                .field("send_timeout", &self.send_timeout)
+                .field("backpressure", &self.backpressure)
+                .field("duplicate_policy", &self.duplicate_policy)
+                .field("capacity_policy", &self.capacity_policy)
+                .field("quota", &self.quota)
+                .field("evict_on_error", &self.evict_on_error)
+                .field("evict_on_timeout", &self.evict_on_timeout)
+                .field("on_evict", &self.on_evict.is_some())
+                .field("on_event", &self.on_event.is_some())
+                .field("heartbeat", &self.heartbeat)
+                .field("history", &self.history)
+                .field("reaper", &self.reaper)
+                .finish()
         }
+    }
 
-        /// iterates through every room and does something with them immutably. You cannot mutate anything inside of it, even rooms and not captured variables.      
-        /// 
-        /// ```rust
-        /// 
-        /// use axum_wsb::normal::Broadcaster;
-        /// use tokio::sync::RwLock;
-        /// use std::sync::Arc;
-        /// 
-        /// fn main () {
-        ///     let receivers: Arc<RwLock<Broadcaster>> = Broadcaster::new();
-        /// 
-        ///     async {
-        ///         receivers.read().await.each_room_immut(|room| println!("hello, {}. guest!", room.id));
-        ///     };
-        /// 
-        /// }
-        /// 
-        /// 
-        /// ```
-        pub fn each_room_immut<F>(&self, f: F) where F: Fn(&Room) {
-            for room in &self.rooms {
-                f(room);
+    /// settings of the heartbeat. Every `interval`, each connection is pinged and the ones which didn't answer `max_missed` pings in a row are closed with `close_frame`.
+    #[derive(Debug, Clone)]
+    pub struct Heartbeat {
+        pub interval: Duration,
+        /// zero is treated as one.
+        pub max_missed: u32,
+        pub close_frame: Option<CloseFrame<'static>>
+    }
+
+    impl Default for Heartbeat {
+        fn default() -> Self {
+            Self {
+                interval: DEFAULT_HEARTBEAT_INTERVAL,
+                max_missed: 3,
+                close_frame: None
             }
         }
+    }
 
-        /// iterates through every room and does something with them immutably. You cannot mutate rooms itself but can mutate captured variables.
-        /// 
-        /// ```rust
-        /// 
-        /// use axum_wsb::normal::Broadcaster;
-        /// use tokio::sync::RwLock;
-        /// use std::sync::Arc;
-        /// 
-        /// fn main () {
-        ///     let receivers: Arc<RwLock<Broadcaster>> = Broadcaster::new();
-        /// 
-        ///     let mut num = 0;
-        /// 
-        ///     async {
-        ///         receivers.read().await.each_room(|room| {
-        ///             num = num + 1;
-        ///         });
-        ///     };
-        /// 
-        /// 
-        ///     println!("here is number: {}", num)
-        /// }
-        /// 
-        /// 
-        /// ```
-        pub fn each_room<F>(&self, mut f: F) where F: FnMut(&Room) {
-            for room in &self.rooms {
-                f(room);
+    /// limits of the connections which a broadcaster takes through `.handle()` and `.serve()`. A connection over them fails with `Error::QuotaExceeded` and it's closed with `close_frame`.
+    #[derive(Debug, Clone)]
+    pub struct Quota {
+        /// how many connections the broadcaster takes at most, `None` is unlimited.
+        pub total: Option<usize>,
+        /// how many connections with the same key the broadcaster takes at most, keys are given by `Broadcaster::set_quota_key()`. `None` is unlimited.
+        pub per_key: Option<usize>,
+        pub close_frame: Option<CloseFrame<'static>>
+    }
+
+    impl Default for Quota {
+        fn default() -> Self {
+            Self {
+                total: None,
+                per_key: None,
+                close_frame: Some(CloseFrame { code: 1008, reason: "too many connections".into() })
             }
         }
+    }
 
-        /// iterates through every room and does something with them mutably. You can mutate everything belong to it. But warning, for now, you cannot send messages to client from it right now and until async closures will be stable probably we're not be able to do it. Because of that, we're not able to give examples for that.
-        pub async fn each_room_mut<F>(&mut self, mut f: F) where F: FnMut(&mut Room) {
-            for room in &mut self.rooms {
-                f(room);
+    /// settings of the reaper. Every `interval`, rooms which are empty for longer than `empty_ttl` are removed and the ones whose `expires_at` in their state has passed are removed after their connections are closed with `close_frame`. Rooms which are created by `.create_room()` or have an `expires_at` aren't removed for being empty.
+    #[derive(Debug, Clone)]
+    pub struct Reaper {
+        pub interval: Duration,
+        /// `None` keeps the empty rooms.
+        pub empty_ttl: Option<Duration>,
+        pub close_frame: Option<CloseFrame<'static>>
+    }
+
+    impl Default for Reaper {
+        fn default() -> Self {
+            Self {
+                interval: DEFAULT_REAPER_INTERVAL,
+                empty_ttl: Some(Duration::from_secs(60)),
+                close_frame: None
             }
         }
+    }
 
-        /// check if a room with given id exist and wrap it in an option.
-        pub fn check_room(&mut self, id: &String) -> Option<&mut Room> {
-            match self.rooms.iter_mut().find(|room| room.id == *id) {
-                Some(room) => Some(room),
-                None => None
-            }
+    /// what broadcasting methods do when the outbound queue of a connection is full, which means the client reads slower than the room talks.
+    #[derive(Debug, Clone, Default)]
+    pub enum Backpressure {
+        /// wait for a place in the queue at most for the `send_timeout` of the config, than fail with `Error::Timeout`. It's the default.
+        #[default]
+        Wait,
+        /// drop the oldest message in the queue for making place to the new one.
+        DropOldest,
+        /// drop the new message, the connection is reported in `dropped` of the `DeliveryReport`.
+        DropNewest,
+        /// drop the pending data messages and queue the new one, so the client skips to the latest message. Pings and pongs are kept.
+        Coalesce,
+        /// drop the pending messages and close the connection with given close frame, it's reported with `Error::QueueFull`.
+        Disconnect(Option<CloseFrame<'static>>)
+    }
+
+    /// builds the message which announces a presence event to the other members of the room. Return `None` for not announcing it.
+    pub type PresenceFormat<T, M = ()> = Arc<dyn Fn(&PresenceEvent<'_, M>) -> Option<T> + Send + Sync>;
+
+    /// the presence format which a broadcaster shares with all of it's rooms.
+    struct Announcer<T, M>(Option<PresenceFormat<T, M>>);
+
+    impl<T, M> Clone for Announcer<T, M> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
         }
+    }
 
-        /// only check if a room exist and if it's return true.
-        pub fn check(&self, id: &String) -> bool {
-            return self.rooms.iter().any(|room| room.id == *id);
+    impl<T, M> Default for Announcer<T, M> {
+        fn default() -> Self {
+            Self(None)
         }
+    }
 
-        /// it removes a room with given id and closes all the connections inside of it.
-        pub async fn remove_room(&mut self, id: &String) {
-            self.rooms.retain_mut(|room| { 
-                if room.id == *id {
-                    let _ = async {
-                        let _ = room.close(None).await;
-                    };
-                    
-                    false 
-                } else { 
-                    true 
-                }
-            });
+    impl<T, M> fmt::Debug for Announcer<T, M> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("Announcer").field(&self.0.is_some()).finish()
         }
+    }
 
-        /// it removes all empty rooms.
-        pub fn remove_empty_rooms(&mut self) {
-            self.rooms.retain(|room| { 
-                if room.connections.is_empty() { 
-                    false 
-                } else { 
-                    true 
-                }
-            });
+    /// decides if a connection can join the room, by looking at the room and the metadata of the connection. Returning `false` refuses it with `Error::Refused`.
+    pub type Admission<T, S, M = (), D = ()> = Arc<dyn Fn(&Room<T, S, M, D>, &Connection<T, S, M>) -> bool + Send + Sync>;
+
+    /// the admission check which a broadcaster shares with all of it's rooms.
+    struct Gate<T, S, M, D>(Option<Admission<T, S, M, D>>);
+
+    impl<T, S, M, D> Clone for Gate<T, S, M, D> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
+        }
+    }
+
+    impl<T, S, M, D> Default for Gate<T, S, M, D> {
+        fn default() -> Self {
+            Self(None)
         }
+    }
 
-        /// Removes the connection from Room. Warning: Because the async closures are not stable yet, we cannot close the connection in that function, you have to make cleanup on your cadebase. For that, check the examples & Documentation.
-        pub fn remove_connection(&mut self, id: &String) -> Option<SplitSink<WebSocket, Message>> {
-            for room in &mut self.rooms {
-                if let Some(pos) = room.connections.iter().position(|connection| connection.id == *id) {
-                    let connection = room.connections.remove(pos);
-                    return Some(connection.receiver);
-                }
-            }
-            None
+    impl<T, S, M, D> fmt::Debug for Gate<T, S, M, D> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("Gate").field(&self.0.is_some()).finish()
+        }
+    }
+
+    /// gives the key which a connection is counted with for the `per_key` quota, such as the ip address, user id or tenant in it's metadata. `None` only counts it in the total.
+    pub type QuotaKey<T, S, M = ()> = Arc<dyn Fn(&Connection<T, S, M>) -> Option<String> + Send + Sync>;
+
+    /// the quota key of a broadcaster.
+    struct Keyer<T, S, M>(Option<QuotaKey<T, S, M>>);
+
+    impl<T, S, M> Clone for Keyer<T, S, M> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
+        }
+    }
+
+    impl<T, S, M> Default for Keyer<T, S, M> {
+        fn default() -> Self {
+            Self(None)
         }
     }
 
-    impl Default for Broadcaster { 
-        fn default() -> Self { 
-            Self { 
-                rooms: vec![], 
-            } 
+    impl<T, S, M> fmt::Debug for Keyer<T, S, M> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("Keyer").field(&self.0.is_some()).finish()
         }
     }
-}
\ No newline at end of file
+
+    /// limits the data messages which `.serve()` reads from each connection with a token bucket. The bucket holds `burst` tokens and it's refilled `per_second` tokens in each second, every message takes a token and the ones which come when it's empty are handled by `action`.
+    #[derive(Debug)]
+    pub struct RateLimit<T> {
+        pub per_second: f64,
+        pub burst: u32,
+        pub action: RateAction<T>
+    }
+
+    /// what `.serve()` does with a message which comes over the rate limit.
+    #[derive(Debug)]
+    pub enum RateAction<T> {
+        /// drop the message silently.
+        Drop,
+        /// drop the message and send the message which given function builds to the client, so it knows it's too fast.
+        Warn(fn() -> T),
+        /// drop the message and close the connection with given close frame.
+        Close(Option<CloseFrame<'static>>)
+    }
+
+    impl<T> Clone for RateLimit<T> {
+        fn clone(&self) -> Self {
+            Self {
+                per_second: self.per_second,
+                burst: self.burst,
+                action: self.action.clone()
+            }
+        }
+    }
+
+    impl<T> Clone for RateAction<T> {
+        fn clone(&self) -> Self {
+            match self {
+                RateAction::Drop => RateAction::Drop,
+                RateAction::Warn(warning) => RateAction::Warn(*warning),
+                RateAction::Close(close_frame) => RateAction::Close(close_frame.clone())
+            }
+        }
+    }
+
+    /// a connection which waits on the waitlist of a room for a free seat. The result of it's admission is sent back when it's promoted.
+    #[derive(Debug)]
+    struct Waiter<T, S, M> {
+        connection: Connection<T, S, M>,
+        last_seen: Option<u64>,
+        promoted: oneshot::Sender<Result<Resume>>
+    }
+
+    /// what `.add_connection()` does when the room already has a connection with the same id.
+    #[derive(Debug, Clone, Default)]
+    pub enum DuplicatePolicy {
+        /// fail with `Error::DuplicateConnection` and drop the new receiver. It's the default.
+        #[default]
+        Reject,
+        /// close the existing connection with given close frame and put the new one in it's place.
+        Replace(Option<CloseFrame<'static>>),
+        /// keep both of them, like different devices of the same user. Messages are sent to each of them, closing or removing the id affects all of them.
+        KeepBoth
+    }
+
+    /// room implementation.
+    #[derive(Debug)]
+    pub struct Room<T, S, M = (), D = ()> {
+        pub id: String,
+        /// how many connections are in `connections`, it's kept up to date with it so the capacity is checked without counting them.
+        count: usize,
+        connections: HashMap<String, Vec<Connection<T, S, M>>>,
+        index: Index,
+        config: Shared<Config>,
+        presence: Shared<Announcer<T, M>>,
+        admission: Shared<Gate<T, S, M, D>>,
+        this: Weak<RwLock<Room<T, S, M, D>>>,
+        removed: bool,
+        persistent: bool,
+        state: RoomState<D>,
+        backpressure: Option<Backpressure>,
+        empty_since: Option<Instant>,
+        waitlist: VecDeque<Waiter<T, S, M>>,
+        history: Backlog<Message<T>>,
+        order: Mutex<()>
+    }
+
+    /// type for each individual connection. The receiver is owned by a writer task, connection only holds the sending half of it's outbound queue.
+    /// Cloning a connection is cheap and the clones share the same socket, so one connection can be in many rooms at once.
+    #[derive(Debug)]
+    pub struct Connection<T, S, M = ()> {
+        pub id: String,
+        meta: Shared<Arc<M>>,
+        sender: Outbox<Message<T>>,
+        writer: AbortHandle,
+        failure: watch::Receiver<Option<Error>>,
+        liveness: Arc<Liveness>,
+        permit: Arc<OnceLock<Permit>>,
+        _marker: PhantomData<fn() -> S>
+    }
+
+    impl<T, S, M> Clone for Connection<T, S, M> {
+        fn clone(&self) -> Self {
+            Self {
+                id: self.id.clone(),
+                meta: self.meta.clone(),
+                sender: self.sender.clone(),
+                writer: self.writer.clone(),
+                failure: self.failure.clone(),
+                liveness: Arc::clone(&self.liveness),
+                permit: Arc::clone(&self.permit),
+                _marker: PhantomData
+            }
+        }
+    }
+
+    impl<T: Display + Serialize + Send + 'static, S: Display + 'static, M: Default> Connection<T, S, M> {
+        /// create a connection and spawn it's writer task with the default queue capacity:
+        pub fn create(id: String, receiver: Receiver<T, S>) -> Self {
+            Self::spawn(id, receiver, M::default(), DEFAULT_QUEUE_CAPACITY)
+        }
+
+        /// create a connection whose outbound queue can hold given amount of messages. Capacity must be greater than zero.
+        pub fn with_capacity(id: String, receiver: Receiver<T, S>, capacity: usize) -> Self {
+            Self::spawn(id, receiver, M::default(), capacity)
+        }
+    }
+
+    impl<T: Display + Serialize + Send + 'static, S: Display + 'static, M> Connection<T, S, M> {
+        /// create a connection which carries given metadata, such as the user, role or locale of the client. Predicates of the conditional methods can filter by it with `.meta()`.
+        pub fn with_meta(id: String, receiver: Receiver<T, S>, meta: M) -> Self {
+            Self::spawn(id, receiver, meta, DEFAULT_QUEUE_CAPACITY)
+        }
+
+        /// spawn the writer task of a new connection.
+        fn spawn(id: String, receiver: Receiver<T, S>, meta: M, capacity: usize) -> Self {
+            let (sender, writer, failure) = crate::spawn_writer(id.clone(), receiver, capacity, |message| matches!(message, Message::Close(_)));
+
+            Self {
+                id,
+                meta: Shared::new(Arc::new(meta)),
+                sender,
+                writer,
+                failure,
+                liveness: Arc::default(),
+                permit: Arc::default(),
+                _marker: PhantomData
+            }
+        }
+    }
+
+    impl<T, S, M> Connection<T, S, M> {
+        /// the metadata of the connection. Clones of the connection in other rooms share it.
+        pub fn meta(&self) -> Arc<M> {
+            self.meta.get()
+        }
+
+        /// change the metadata of the connection, every clone of it sees the new one. Use `Broadcaster::update_meta()` for announcing it to the rooms it's in.
+        pub fn set_meta(&self, meta: M) {
+            self.meta.set(Arc::new(meta));
+        }
+
+        /// when the last pong is received from the client.
+        pub fn last_pong(&self) -> Option<Instant> {
+            self.liveness.last_pong()
+        }
+
+        /// round trip time of the last heartbeat ping which is answered.
+        pub fn latency(&self) -> Option<Duration> {
+            self.liveness.latency()
+        }
+
+        /// how many heartbeat pings are not answered in a row.
+        pub fn missed_heartbeats(&self) -> u32 {
+            self.liveness.missed()
+        }
+
+        /// record a pong which is received from the client. `.serve()` does it by itself, call it from your own read loop if you use the heartbeat with `.handle()`.
+        pub fn record_pong(&self, payload: &[u8]) {
+            self.liveness.pong(payload);
+        }
+
+        /// check if both of them are the same connection, which means they share the same socket.
+        fn is(&self, other: &Self) -> bool {
+            self.failure.same_channel(&other.failure)
+        }
+
+        /// put the message into outbound queue of the connection. It fails if the queue is full or the writer task is stopped.
+        fn enqueue(&self, message: Message<T>) -> Result<()> {
+            self.sender.offer(&self.id, message, Overflow::Reject).map(|_| ())
+        }
+
+        /// put the message into outbound queue of the connection by the backpressure policy without waiting. A message which has to wait for a place in the queue is given a ticket for `.settle()`.
+        fn queue(&self, message: Message<T>, backpressure: &Backpressure, config: &Config) -> Result<Queued> {
+            let overflow = match backpressure {
+                Backpressure::Wait if config.send_timeout.is_zero() => Overflow::Reject,
+                Backpressure::Wait => return Ok(self.sender.reserve(&self.id, message)?.map_or(Queued::Taken(true), Queued::Waiting)),
+                Backpressure::DropOldest => Overflow::DropOldest,
+                Backpressure::DropNewest => Overflow::DropNewest,
+                Backpressure::Coalesce => Overflow::Coalesce(|message| matches!(message, Message::Item(_))),
+                Backpressure::Disconnect(close_frame) => Overflow::Close(Message::Close(close_frame.clone()))
+            };
+
+            let offered = self.sender.offer(&self.id, message, overflow);
+
+            if let (Backpressure::Disconnect(_), Err(Error::QueueFull(_))) = (backpressure, &offered) {
+                tokio::spawn(crate::abort_after(self.writer.clone(), self.failure.clone(), config.close_timeout));
+            }
+
+            offered.map(Queued::Taken)
+        }
+
+        /// wait until the message which is queued by `.queue()` takes it's place, at most for the `send_timeout` of the config. `false` is returned if the message is dropped because the queue is full.
+        async fn settle(&self, queued: Result<Queued>, config: &Config) -> Result<bool> {
+            match queued? {
+                Queued::Taken(taken) => Ok(taken),
+                Queued::Waiting(ticket) => self.sender.wait_turn(&self.id, ticket, config.send_timeout).await.map(|_| true)
+            }
+        }
+
+        /// how many messages wait in the outbound queue of the connection.
+        pub fn queued(&self) -> usize {
+            self.sender.queued()
+        }
+
+        /// how many messages are dropped from the outbound queue of the connection because it was full, by the backpressure policy of the rooms it's in. Clones of the connection share it.
+        pub fn dropped(&self) -> u64 {
+            self.sender.dropped()
+        }
+
+        /// close the connection gracefully. The messages which are queued before are written first, than the close frame is sent and the socket is closed. If all of that can't be done before the timeout, the writer is stopped, the socket is dropped and `Error::Timeout` is returned.
+        pub async fn close(self, close_frame: Option<CloseFrame<'static>>, timeout: Duration) -> Result<()> {
+            crate::shutdown_writer(&self.id, self.sender, &self.writer, self.failure, Message::Close(close_frame), timeout).await
+        }
+
+        /// send the text message.
+        pub async fn send(&self, message: T) -> Result<()> where T: Clone {
+            self.enqueue(Message::Item(message))
+        }
+
+        /// send the text message if the given condition in it's closure is true.
+        pub async fn send_if<F>(&self, message: T, condition: F) -> Result<()> where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            match condition(self) {
+                true => self.enqueue(Message::Item(message)),
+                false => Ok(())
+            }
+        }
+
+        /// sen the text message if the given condition in it's closure is true.
+        pub async fn send_if_not<F>(&self, message: T, condition: F) -> Result<()> where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            match condition(self) {
+                false => self.enqueue(Message::Item(message)),
+                true => Ok(())
+            }
+        }
+
+        /// send the text message.
+        pub async fn ping(&self, message: &[u8]) -> Result<()> where T: Clone {
+            self.enqueue(Message::Ping(message.to_vec()))
+        }
+
+        /// send the text message if the given condition in it's closure is true.
+        pub async fn ping_if<F>(&self, message: &[u8], condition: F) -> Result<()> where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            match condition(self) {
+                true => self.enqueue(Message::Ping(message.to_vec())),
+                false => Ok(())
+            }
+        }
+
+        /// sen the text message if the given condition in it's closure is true.
+        pub async fn ping_if_not<F>(&self, message: &[u8], condition: F) -> Result<()> where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            match condition(self) {
+                false => self.enqueue(Message::Ping(message.to_vec())),
+                true => Ok(())
+            }
+        }
+
+        /// send the text message.
+        pub async fn pong(&self, message: &[u8]) -> Result<()> where T: Clone {
+            self.enqueue(Message::Pong(message.to_vec()))
+        }
+
+        /// send the text message if the given condition in it's closure is true.
+        pub async fn pong_if<F>(&self, message: &[u8], condition: F) -> Result<()> where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            match condition(self) {
+                true => self.enqueue(Message::Pong(message.to_vec())),
+                false => Ok(())
+            }
+        }
+
+        /// sen the text message if the given condition in it's closure is true.
+        pub async fn pong_if_not<F>(&self, message: &[u8], condition: F) -> Result<()> where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            match condition(self) {
+                false => self.enqueue(Message::Pong(message.to_vec())),
+                true => Ok(())
+            }
+        }
+    }
+
+    impl<T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static> Room<T, S, M, D> {
+        /// add a connection to the room with given id. If the room already has a connection with that id, the `duplicate_policy` of the config decides what happens: by default the receiver is dropped and `Error::DuplicateConnection` is returned:
+        pub fn add_connection(&mut self, id: &str, receiver: Receiver<T, S>) -> Result<()> where M: Default {
+            self.join(Connection::create(id.to_string(), receiver))
+        }
+
+        /// add a connection to the room like `.add_connection()`, with given metadata.
+        pub fn add_connection_with_meta(&mut self, id: &str, receiver: Receiver<T, S>, meta: M) -> Result<()> {
+            self.join(Connection::with_meta(id.to_string(), receiver, meta))
+        }
+
+        /// put an existing connection into the room too, such as a clone of a connection from another room. It shares the socket with the other rooms it's in. Joining a room which it's already in does nothing, another connection with the same id is handled by the `duplicate_policy` of the config.
+        pub fn join(&mut self, connection: Connection<T, S, M>) -> Result<()> {
+            self.admit(connection, None).map(|_| ())
+        }
+
+        /// put the connection into the room like `.join()`, but instead of the usual replay, send it the messages which are broadcasted after the last sequence number it has seen, such as when a client reconnects. If some of them are not kept anymore, nothing is replayed and `Resume::GapTooLarge` is returned, so the client can load everything again.
+        pub fn resume(&mut self, connection: Connection<T, S, M>, last_seen: u64) -> Result<Resume> {
+            self.admit(connection, Some(last_seen))
+        }
+
+        /// put the connection into the room if it has a free seat and no one waits for it, than seat it.
+        fn admit(&mut self, connection: Connection<T, S, M>, last_seen: Option<u64>) -> Result<Resume> {
+            let existing = self.connections.get(&connection.id);
+
+            if existing.is_some_and(|existing| existing.iter().any(|existing| existing.is(&connection))) {
+                return Ok(Resume::Replayed(0));
+            }
+
+            // a connection which replaces another one takes it's seat.
+            let replacing = existing.is_some() && matches!(self.config.get().duplicate_policy, DuplicatePolicy::Replace(_));
+
+            self.promote();
+
+            if !replacing && (self.is_full() || self.waiting() > 0) {
+                return Err(Error::RoomFull(self.id.clone()));
+            }
+
+            self.seat(connection, last_seen)
+        }
+
+        /// put the connection into the room by the admission check and the duplicate policy, and replay the history to it.
+        fn seat(&mut self, connection: Connection<T, S, M>, last_seen: Option<u64>) -> Result<Resume> {
+            let config = self.config.get();
+            let existing = self.connections.contains_key(&connection.id);
+
+            if let (true, DuplicatePolicy::Reject) = (existing, &config.duplicate_policy) {
+                return Err(Error::DuplicateConnection(connection.id.clone()));
+            }
+
+            if self.admission.get().0.is_some_and(|check| !check(self, &connection)) {
+                return Err(Error::Refused(connection.id.clone()));
+            }
+
+            if let (true, DuplicatePolicy::Replace(close_frame)) = (existing, config.duplicate_policy) {
+                // the new connection takes the place of the old ones right away, so the room is not told to be empty.
+                let replaced = self.detach(&connection.id);
+
+                self.departed(&replaced);
+
+                for existing in replaced {
+                    tokio::spawn(existing.close(close_frame.clone(), config.close_timeout));
+                }
+            }
+
+            Ok(self.insert(connection, last_seen))
+        }
+
+        /// put the connection on the waitlist of the room, it's admission is sent to the returned receiver when a seat frees up. The waiters which stopped waiting are dropped first.
+        fn wait(&mut self, connection: Connection<T, S, M>, last_seen: Option<u64>) -> oneshot::Receiver<Result<Resume>> {
+            let (promoted, receiver) = oneshot::channel();
+
+            self.waitlist.retain(|waiter| !waiter.promoted.is_closed());
+            self.waitlist.push_back(Waiter { connection, last_seen, promoted });
+
+            receiver
+        }
+
+        /// give the free seats to the connections on the waitlist, in the order they came. The ones which stopped waiting are skipped.
+        fn promote(&mut self) {
+            while !self.is_full() {
+                let Some(waiter) = self.waitlist.pop_front() else {
+                    return;
+                };
+
+                if waiter.promoted.is_closed() {
+                    continue;
+                }
+
+                let id = waiter.connection.id.clone();
+                let failure = waiter.connection.failure.clone();
+                let admission = self.seat(waiter.connection, waiter.last_seen);
+
+                // it can stop waiting in the meantime, than it's seat is freed again.
+                if let Err(Ok(_)) = waiter.promoted.send(admission) {
+                    self.evict(&id, &failure);
+                }
+            }
+        }
+
+        /// put the connection into the room and the index and start watching it's writer. The history, or the messages after the last seen sequence number, is replayed to it first, as much as it's queue takes.
+        fn insert(&mut self, connection: Connection<T, S, M>, last_seen: Option<u64>) -> Resume {
+            let history = self.config.get().history;
+            let max_age = history.as_ref().and_then(|history| history.max_age);
+
+            let replay = match last_seen {
+                Some(last_seen) => self.history.since(last_seen, max_age).map_err(|oldest| Resume::GapTooLarge { last_seen, oldest }),
+                None => Ok(self.history.recent(history.map_or(0, |history| history.replay), max_age))
+            };
+
+            let resume = match replay {
+                // a resumed connection gets all the messages it missed or none of them, so it never skips some of them silently.
+                Ok(messages) if last_seen.is_some() && messages.len() > connection.sender.free() => Resume::TooManyMissed { missed: messages.len(), free: connection.sender.free() },
+                Ok(messages) => Resume::Replayed(messages.into_iter().map_while(|message| connection.enqueue(message).ok()).count()),
+                Err(gap) => gap
+            };
+
+            self.announce(PresenceKind::Joined, &connection);
+            self.empty_since = None;
+
+            crate::emit(&self.config.get().on_event, || Event::Joined { room: self.id.clone(), connection: connection.id.clone() });
+
+            self.watch(&connection);
+            self.index.insert(&connection.id, &self.id);
+            self.connections.entry(connection.id.clone()).or_default().push(connection);
+            self.count += 1;
+
+            resume
+        }
+
+        /// wait in the background until the writer of the connection stops. If it's failed, the connection is evicted when the config says so. If it's closed, it's
+        /// removed silently, since it can be closed through another room it's in.
+        fn watch(&self, connection: &Connection<T, S, M>) {
+            let room = self.this.clone();
+            let room_id = self.id.clone();
+            let config = self.config.clone();
+            let id = connection.id.clone();
+            let failure = connection.failure.clone();
+
+            tokio::spawn(async move {
+                let Some(error) = crate::writer_failure(failure.clone()).await else {
+                    if let Some(room) = room.upgrade() {
+                        room.write().await.evict(&id, &failure);
+                    }
+
+                    return;
+                };
+
+                let config = config.get();
+
+                crate::emit(&config.on_event, || Event::SendFailed { room: room_id, connection: id.clone(), error: error.clone() });
+
+                if !config.evict_on_error {
+                    return;
+                }
+
+                evict(room, vec![(id, failure, error)], config.on_evict).await;
+            });
+        }
+
+        /// remove the connection if it's writer is the one which published given failure. A connection which joined later with the same id is kept.
+        fn evict(&mut self, id: &String, failure: &watch::Receiver<Option<Error>>) -> Option<Connection<T, S, M>> {
+            let connections = self.connections.get_mut(id)?;
+            let pos = connections.iter().position(|connection| connection.failure.same_channel(failure))?;
+            let connection = connections.remove(pos);
+
+            self.count -= 1;
+
+            if connections.is_empty() {
+                self.connections.remove(id);
+                self.index.remove(id, &self.id);
+            }
+
+            self.left(std::slice::from_ref(&connection));
+            self.promote();
+
+            Some(connection)
+        }
+
+        /// remove the connections with given id from room. If they're in other rooms too, they stay there:
+        pub fn remove_connection(&mut self, id: String) {
+            self.take(&id);
+        }
+
+        /// check if a connection exist and return if it's.
+        pub fn check_connection(&mut self, id: &String) -> Option<&Connection<T, S, M>> {
+            self.connections.get(id)?.first()
+        }
+
+        /// iterate through the connections of the room, in no particular order.
+        pub fn connections(&self) -> impl Iterator<Item = &Connection<T, S, M>> {
+            self.connections.values().flatten()
+        }
+
+        /// how many connections the room has. Connections which share an id are counted one by one.
+        pub fn len(&self) -> usize {
+            self.count
+        }
+
+        /// check if the room has no connections.
+        pub fn is_empty(&self) -> bool {
+            self.connections.is_empty()
+        }
+
+        /// check if the room is at it's capacity.
+        pub fn is_full(&self) -> bool {
+            self.state.capacity.is_some_and(|capacity| self.len() >= capacity)
+        }
+
+        /// how many connections wait on the waitlist of the room. Seats which are added by raising the capacity are given to them on the next join or leave.
+        pub fn waiting(&self) -> usize {
+            self.waitlist.iter().filter(|waiter| !waiter.promoted.is_closed()).count()
+        }
+
+        /// since when the room is empty, `None` if it has connections.
+        pub fn empty_since(&self) -> Option<Instant> {
+            self.empty_since
+        }
+
+        /// the members of the room with their metadata. Connections which share an id are listed once, with the metadata of the first one.
+        pub fn members(&self) -> impl Iterator<Item = (&String, Arc<M>)> {
+            self.connections.iter().filter_map(|(id, connections)| Some((id, connections.first()?.meta())))
+        }
+
+        /// announce the connections which are taken out of the room and tell the event callback about them, and about the room if it's empty now.
+        fn left(&mut self, connections: &[Connection<T, S, M>]) {
+            self.departed(connections);
+
+            if !connections.is_empty() && self.connections.is_empty() {
+                self.empty_since = Some(Instant::now());
+
+                crate::emit(&self.config.get().on_event, || Event::RoomEmptied { room: self.id.clone() });
+            }
+        }
+
+        /// announce the connections which are taken out of the room and tell the event callback that they left.
+        fn departed(&self, connections: &[Connection<T, S, M>]) {
+            let hook = self.config.get().on_event;
+
+            for connection in connections {
+                self.announce(PresenceKind::Left, connection);
+
+                crate::emit(&hook, || Event::Left { room: self.id.clone(), connection: connection.id.clone() });
+            }
+        }
+
+        /// announce the event of the connection to the other members of the room, if the broadcaster has a presence format. Announcements skip the backpressure policy, they're dropped if a queue is full.
+        fn announce(&self, kind: PresenceKind, connection: &Connection<T, S, M>) {
+            let Some(format) = self.presence.get().0 else {
+                return;
+            };
+
+            let meta = connection.meta();
+
+            let event = PresenceEvent {
+                kind,
+                room: &self.id,
+                id: &connection.id,
+                meta: meta.as_ref()
+            };
+
+            for member in self.connections().filter(|member| !member.is(connection)) {
+                // typed messages can't be cloned without a bound, so the message is built for each member.
+                if let Some(message) = format(&event) {
+                    let _ = member.enqueue(Message::Item(message));
+                }
+            }
+        }
+
+        /// the state of the room, such as it's title, owner and topic.
+        pub fn state(&self) -> &RoomState<D> {
+            &self.state
+        }
+
+        /// the state of the room, for changing it. It's kept as long as the room lives.
+        pub fn state_mut(&mut self) -> &mut RoomState<D> {
+            &mut self.state
+        }
+
+        /// the backpressure policy which broadcasting methods of the room use.
+        pub fn backpressure(&self) -> Backpressure {
+            self.backpressure.clone().unwrap_or_else(|| self.config.get().backpressure)
+        }
+
+        /// use given backpressure policy in that room instead of the one in the config, `None` goes back to the config.
+        pub fn set_backpressure(&mut self, backpressure: Option<Backpressure>) {
+            self.backpressure = backpressure;
+        }
+
+        /// the messages which are kept in the history of the room, oldest first.
+        pub fn history(&self) -> Vec<T> {
+            let max_age = self.config.get().history.and_then(|history| history.max_age);
+
+            self.history.recent(usize::MAX, max_age).into_iter().filter_map(|message| match message {
+                Message::Item(item) => Some(item),
+                _ => None
+            }).collect()
+        }
+
+        /// send the last `count` messages of the history to the connections with given id, such as when a client asks for the messages before it joined.
+        pub fn replay(&self, id: &String, count: usize) -> Result<()> {
+            let connections = self.connections.get(id).ok_or_else(|| Error::UnknownConnection(id.clone()))?;
+            let max_age = self.config.get().history.and_then(|history| history.max_age);
+
+            for connection in connections {
+                for message in self.history.recent(count, max_age) {
+                    connection.enqueue(message)?;
+                }
+            }
+
+            Ok(())
+        }
+
+        /// forget the history of the room.
+        pub fn clear_history(&self) {
+            self.history.clear();
+        }
+
+        /// sequence number of the last broadcast to the whole room, zero if there's none yet.
+        pub fn sequence(&self) -> u64 {
+            self.history.sequence()
+        }
+
+        /// broadcast the message which is built with it's sequence number to every connection and keep it in the history if it's on. Such broadcasts of a room are queued one by one, so every connection gets them in the order of their sequence numbers.
+        /// They're only ordered while the message is stamped and queued, a message which waits for a place in the queue of a slow connection waits behind the earlier ones after that, so it doesn't hold up the other broadcasts.
+        async fn deliver_sequenced<F>(&self, message: F) -> DeliveryReport where F: FnOnce(u64) -> Message<T>, T: Clone {
+            let config = self.config.get();
+            let backpressure = self.backpressure();
+
+            let (sequence, queued) = {
+                let _order = self.order.lock().unwrap_or_else(PoisonError::into_inner);
+                let (sequence, message) = self.history.record(message, config.history.as_ref());
+
+                (sequence, queue_all(self.connections().map(|connection| (&self.this, self.id.as_str(), connection)), message, &backpressure, &config))
+            };
+
+            let mut report = fan_out(queued, &config).await;
+
+            report.sequence = Some(sequence);
+
+            report
+        }
+
+        /// take the connections with given id out of the room and the index, and give their seats to the waitlist.
+        fn take(&mut self, id: &String) -> Vec<Connection<T, S, M>> {
+            let connections = self.detach(id);
+
+            self.left(&connections);
+            self.promote();
+
+            connections
+        }
+
+        /// take the connections with given id out of the room and the index, without announcing them or promoting the waitlist.
+        fn detach(&mut self, id: &String) -> Vec<Connection<T, S, M>> {
+            let connections = self.connections.remove(id).unwrap_or_default();
+
+            self.count -= connections.len();
+
+            if !connections.is_empty() {
+                self.index.remove(id, &self.id);
+            }
+
+            connections
+        }
+
+        /// take every connection which satisfies the condition out of the room and the index.
+        fn take_where<F>(&mut self, condition: F) -> Vec<Connection<T, S, M>> where F: Fn(&Connection<T, S, M>) -> bool {
+            let mut taken = vec![];
+
+            for connections in self.connections.values_mut() {
+                let (matched, remaining): (Vec<_>, Vec<_>) = std::mem::take(connections).into_iter().partition(|connection| condition(connection));
+
+                *connections = remaining;
+                taken.extend(matched);
+            }
+
+            self.count -= taken.len();
+
+            self.connections.retain(|id, connections| match connections.is_empty() {
+                true => {
+                    self.index.remove(id, &self.id);
+
+                    false
+                },
+                false => true
+            });
+
+            self.left(&taken);
+            self.promote();
+
+            taken
+        }
+
+        /// push the message into the queue of every connection which satisfies the condition, concurrently, and report what happened for each of them. A full queue is handled by the backpressure policy of the room.
+        async fn deliver<F>(&self, message: Message<T>, condition: F) -> DeliveryReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            let config = self.config.get();
+            let backpressure = self.backpressure.clone().unwrap_or_else(|| config.backpressure.clone());
+            let (targets, skipped): (Vec<_>, Vec<_>) = self.connections.values().flatten().partition(|connection| condition(connection));
+            let mut report = fan_out(queue_all(targets.into_iter().map(|connection| (&self.this, self.id.as_str(), connection)), message, &backpressure, &config), &config).await;
+
+            report.skipped = skipped.into_iter().map(|connection| connection.id.clone()).collect();
+
+            report
+        }
+
+        /// broadcast the message directly:
+        pub async fn broadcast(&self, message: &T) -> DeliveryReport where T: Clone {
+            self.deliver_sequenced(|_| Message::Item(message.clone())).await
+        }
+
+        /// broadcast the message which is built with it's sequence number, so the client can tell it when it resumes.
+        pub async fn broadcast_sequenced<F>(&self, message: F) -> DeliveryReport where F: FnOnce(u64) -> T, T: Clone {
+            self.deliver_sequenced(|sequence| Message::Item(message(sequence))).await
+        }
+
+        /// broadcast the message if the given condition in it's closure is true.
+        pub async fn broadcast_if<F>(&self, message: &T, condition: F) -> DeliveryReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            self.deliver(Message::Item(message.clone()), condition).await
+        }
+
+        /// broadcast the message if the given condition in it's closure is false.
+        pub async fn broadcast_if_not<F>(&self, message: &T, condition: F) -> DeliveryReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            self.deliver(Message::Item(message.clone()), |connection| !condition(connection)).await
+        }
+
+        /// broadcast the message directly:
+        pub async fn ping(&self, message: &[u8]) -> DeliveryReport where T: Clone {
+            self.deliver(Message::Ping(message.to_vec()), |_| true).await
+        }
+
+        /// broadcast the message if the given condition in it's closure is true.
+        pub async fn ping_if<F>(&self, message: &[u8], condition: F) -> DeliveryReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            self.deliver(Message::Ping(message.to_vec()), condition).await
+        }
+
+        /// broadcast the message if the given condition in it's closure is false.
+        pub async fn ping_if_not<F>(&self, message: &[u8], condition: F) -> DeliveryReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            self.deliver(Message::Ping(message.to_vec()), |connection| !condition(connection)).await
+        }
+
+        /// broadcast the pong message directly:
+        pub async fn pong(&self, message: &[u8]) -> DeliveryReport where T: Clone {
+            self.deliver(Message::Pong(message.to_vec()), |_| true).await
+        }
+
+        /// broadcast the pong message if the given condition in it's closure is true.
+        pub async fn pong_if<F>(&self, message: &[u8], condition: F) -> DeliveryReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            self.deliver(Message::Pong(message.to_vec()), condition).await
+        }
+
+        /// broadcast the pong message if the given condition in it's closure is false.
+        pub async fn pong_if_not<F>(&self, message: &[u8], condition: F) -> DeliveryReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            self.deliver(Message::Pong(message.to_vec()), |connection| !condition(connection)).await
+        }
+
+        /// it's most convenient way to close a single connection but keeping room open. If more than one connection has that id, all of them are closed and the first error is returned. Closing a connection closes it's socket, so it leaves the other rooms it's in too.
+        pub async fn close_conn(&mut self, close_frame: Option<CloseFrame<'static>>, id: &String) -> Result<()> where T: Clone {
+            match self.take(id) {
+                connections if connections.is_empty() => Err(Error::UnknownConnection(id.clone())),
+                connections => match close_all(connections, close_frame, self.config.get().close_timeout).await.unclean.into_iter().next() {
+                    Some((_, error)) => Err(error),
+                    None => Ok(())
+                }
+            }
+        }
+
+        /// Close all connections and remove it from it's room but not close it.
+        pub async fn close(&mut self, close_frame: Option<CloseFrame<'static>>) -> CloseReport where T: Clone {
+            let connections = self.take_where(|_| true);
+
+            close_all(connections, close_frame, self.config.get().close_timeout).await
+        }
+
+        /// close each connection and remove them from room if the given condition in it's closure is true.
+        pub async fn close_if<F>(&mut self, close_frame: Option<CloseFrame<'static>>, condition: F) -> CloseReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            let connections = self.take_where(condition);
+
+            close_all(connections, close_frame, self.config.get().close_timeout).await
+        }
+
+        /// close each connection and remove them from room if the given condition in it's closure is false.
+        pub async fn close_if_not<F>(&mut self, close_frame: Option<CloseFrame<'static>>, condition: F) -> CloseReport where F: Fn(&Connection<T, S, M>) -> bool, T: Clone {
+            let connections = self.take_where(|connection| !condition(connection));
+
+            close_all(connections, close_frame, self.config.get().close_timeout).await
+        }
+    }
+
+    /// put the message into the queue of each connection by given backpressure policy, without waiting for any of them.
+    fn queue_all<'a, T, S, M, D, I>(targets: I, message: Message<T>, backpressure: &Backpressure, config: &Config) -> Vec<Queueing<'a, T, S, M, D>> where I: IntoIterator<Item = (&'a WeakRoom<T, S, M, D>, &'a str, &'a Connection<T, S, M>)>, T: Display + Serialize + Send + Clone + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        targets.into_iter().map(|(room, room_id, connection)| (room, room_id, connection, connection.queue(message.clone(), backpressure, config))).collect()
+    }
+
+    /// wait until the messages which are queued by `queue_all()` take their places, concurrently, and report what happened for each connection. Failed sends are told to the event callback with the room of the connection
+    /// and the ones which timed out are evicted from that room if `evict_on_timeout` is on. Both the broadcasting methods of `Room` and `.send_to()` of `Broadcaster` deliver through it.
+    async fn fan_out<T, S, M, D>(queued: Vec<Queueing<'_, T, S, M, D>>, config: &Config) -> DeliveryReport where T: Display + Serialize + Send + Clone + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        let mut report = DeliveryReport::default();
+        let mut sending: FuturesUnordered<_> = queued.into_iter().map(|(room, room_id, connection, queued)| async move {
+            (room, room_id, connection, connection.settle(queued, config).await)
+        }).collect();
+
+        let mut timed_out = HashMap::new();
+
+        while let Some((room, room_id, connection, result)) = sending.next().await {
+            match result {
+                Ok(true) => report.delivered.push(connection.id.clone()),
+                Ok(false) => report.dropped.push(connection.id.clone()),
+                Err(error) => {
+                    crate::emit(&config.on_event, || Event::SendFailed { room: room_id.to_string(), connection: connection.id.clone(), error: error.clone() });
+
+                    if config.evict_on_timeout && matches!(error, Error::Timeout(_)) {
+                        // they're grouped by their rooms, so each room is locked once for evicting them.
+                        timed_out.entry(room_id).or_insert_with(|| (room, vec![])).1.push((connection.id.clone(), connection.failure.clone(), error.clone()));
+                    }
+
+                    report.failed.push((connection.id.clone(), error));
+                }
+            }
+        }
+
+        for (room, evictions) in timed_out.into_values() {
+            tokio::spawn(evict(room.clone(), evictions, config.on_evict.clone()));
+        }
+
+        report
+    }
+
+    /// remove the given connections from the room and call `on_evict` for each of them which was still there. Their writers are stopped, so a stalled socket is dropped immediately.
+    async fn evict<T, S, M, D>(room: Weak<RwLock<Room<T, S, M, D>>>, evictions: Evictions, on_evict: Option<EvictHook>) where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        let Some(room) = room.upgrade() else {
+            return;
+        };
+
+        let (room_id, evicted) = {
+            let mut room = room.write().await;
+
+            let evicted: Vec<(String, Error)> = evictions.into_iter().filter_map(|(id, failure, error)| {
+                room.evict(&id, &failure)?.writer.abort();
+
+                Some((id, error))
+            }).collect();
+
+            (room.id.clone(), evicted)
+        };
+
+        if let Some(on_evict) = on_evict {
+            for (connection, error) in evicted {
+                on_evict(&Eviction { room: room_id.clone(), connection, error });
+            }
+        }
+    }
+
+    /// ping every connection of the broadcaster on each tick and close the ones which missed too many pings, until the heartbeat is turned off or the broadcaster is dropped.
+    async fn heartbeat<T, S, M, D>(broadcaster: Weak<RwLock<Broadcaster<T, S, M, D>>>, config: Shared<Config>) where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        loop {
+            let Some(settings) = config.get().heartbeat else {
+                return;
+            };
+
+            tokio::time::sleep(settings.interval).await;
+
+            let Some(broadcaster) = broadcaster.upgrade() else {
+                return;
+            };
+
+            let rooms: Vec<RoomHandle<T, S, M, D>> = broadcaster.read().await.rooms().cloned().collect();
+
+            drop(broadcaster);
+
+            let mut pinged = HashSet::new();
+            let mut stale = vec![];
+
+            for room in rooms {
+                for connection in room.read().await.connections() {
+                    if !pinged.insert(Arc::as_ptr(&connection.liveness) as usize) {
+                        continue;
+                    }
+
+                    let (missed, payload) = connection.liveness.ping();
+
+                    match missed >= settings.max_missed.max(1) {
+                        true => stale.push(connection.clone()),
+                        false => {
+                            let _ = connection.enqueue(Message::Ping(payload.to_vec()));
+                        }
+                    }
+                }
+            }
+
+            let close_timeout = config.get().close_timeout;
+
+            for connection in stale {
+                tokio::spawn(connection.close(settings.close_frame.clone(), close_timeout));
+            }
+        }
+    }
+
+    /// remove the rooms which are empty for too long and close the expired ones on each tick, until the reaper is turned off or the broadcaster is dropped.
+    async fn reaper<T, S, M, D>(broadcaster: Weak<RwLock<Broadcaster<T, S, M, D>>>, config: Shared<Config>) where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        loop {
+            let Some(settings) = config.get().reaper else {
+                return;
+            };
+
+            tokio::time::sleep(settings.interval).await;
+
+            let Some(broadcaster) = broadcaster.upgrade() else {
+                return;
+            };
+
+            let rooms: Vec<RoomHandle<T, S, M, D>> = broadcaster.read().await.rooms().cloned().collect();
+            let now = SystemTime::now();
+            let mut stale = vec![];
+            let mut expired = vec![];
+
+            for room in rooms {
+                let room = room.read().await;
+
+                if room.state.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                    expired.push(room.id.clone());
+                } else if !room.persistent && room.state.expires_at.is_none() && room.empty_since.zip(settings.empty_ttl).is_some_and(|(since, ttl)| since.elapsed() >= ttl) {
+                    stale.push(room.id.clone());
+                }
+            }
+
+            let expired: Vec<RoomHandle<T, S, M, D>> = {
+                let mut this = broadcaster.write().await;
+
+                // a connection can join a stale room in the meantime, it's checked again while the room is locked.
+                for id in &stale {
+                    this.remove_if_empty(id).await;
+                }
+
+                expired.iter().filter_map(|id| this.rooms.remove(id)).collect()
+            };
+
+            drop(broadcaster);
+
+            for room in expired {
+                let (id, connections) = {
+                    let mut room = room.write().await;
+
+                    room.removed = true;
+                    room.waitlist.clear();
+
+                    (room.id.clone(), room.take_where(|_| true))
+                };
+
+                crate::emit(&config.get().on_event, || Event::RoomRemoved { room: id });
+
+                tokio::spawn(close_all(connections, settings.close_frame.clone(), config.get().close_timeout));
+            }
+        }
+    }
+
+    /// close the given connections concurrently and collect which of them are closed cleanly.
+    async fn close_all<T, S, M>(connections: Vec<Connection<T, S, M>>, close_frame: Option<CloseFrame<'static>>, timeout: Duration) -> CloseReport {
+        let closing = connections.into_iter().map(|connection| {
+            let close_frame = close_frame.clone();
+
+            async move {
+                let id = connection.id.clone();
+
+                (id, connection.close(close_frame, timeout).await)
+            }
+        });
+
+        let mut report = CloseReport::default();
+
+        for (id, result) in join_all(closing).await {
+            match result {
+                Ok(()) => report.clean.push(id),
+                Err(error) => report.unclean.push((id, error))
+            }
+        }
+
+        report
+    }
+
+    impl<T, S> Broadcaster<T, S> {
+        /// get receiver and stream, similar to ".handle()" method of actix-ws.
+        pub fn configure(socket: WebSocket<T, S>) -> (Receiver<T, S>, SplitStream<WebSocket<T, S>>) where WebSocket<T, S>: Sink<Message<T>> + Stream + Sized {
+            socket.split()
+        }
+    }
+
+    impl<T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static> Broadcaster<T, S, M, D> {
+        /// handle the all thing. If you use that api, there is no need to any other configuration for grouping and identifying connections. Keep the returned `Membership` while the socket is handled, the connection is removed from it's rooms when it's dropped:
+        pub async fn handle(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: Receiver<T, S>) -> Result<Membership<T, S, M, D>> where M: Default, D: Default {
+            Self::handle_with_meta(broadcaster, room_id, conn_id, receiver, M::default()).await
+        }
+
+        /// handle the connection like `.handle()`, with given metadata. Predicates of the conditional methods can filter the connection by it:
+        pub async fn handle_with_meta(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: Receiver<T, S>, meta: M) -> Result<Membership<T, S, M, D>> where D: Default {
+            Self::enter(broadcaster, room_id, Connection::with_meta(conn_id.to_string(), receiver, meta), None).await.map(|(membership, _)| membership)
+        }
+
+        /// handle the connection like `.handle()`, but instead of the usual replay, send it the messages which are broadcasted in the room after the last sequence number it has seen, such as when a client reconnects. `Resume` tells if they're replayed or the gap is too large for that.
+        pub async fn resume(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: Receiver<T, S>, last_seen: u64) -> Result<(Membership<T, S, M, D>, Resume)> where M: Default, D: Default {
+            Self::resume_with_meta(broadcaster, room_id, conn_id, receiver, M::default(), last_seen).await
+        }
+
+        /// resume the connection like `.resume()`, with given metadata.
+        pub async fn resume_with_meta(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: Receiver<T, S>, meta: M, last_seen: u64) -> Result<(Membership<T, S, M, D>, Resume)> where D: Default {
+            Self::enter(broadcaster, room_id, Connection::with_meta(conn_id.to_string(), receiver, meta), Some(last_seen)).await
+        }
+
+        /// drive the socket by itself: the connection joins the room with given id, every data message is given to the handler and when the client closes the socket or reading it fails, the connection is removed from it's rooms. Pings are answered by the socket itself as long as it's read, so pings and pongs don't reach the handler:
+        pub async fn serve<F, Fut>(broadcaster: &Arc<RwLock<Self>>, socket: WebSocket<T, S>, room_id: &str, conn_id: &str, handler: F) -> Result<()> where F: FnMut(Session<T, S, M, D>, S) -> Fut, Fut: Future<Output = ()>, M: Default, D: Default, S: DeserializeOwned {
+            Self::serve_with_meta(broadcaster, socket, room_id, conn_id, M::default(), handler).await
+        }
+
+        /// serve the socket like `.serve()`, with given metadata.
+        pub async fn serve_with_meta<F, Fut>(broadcaster: &Arc<RwLock<Self>>, socket: WebSocket<T, S>, room_id: &str, conn_id: &str, meta: M, mut handler: F) -> Result<()> where F: FnMut(Session<T, S, M, D>, S) -> Fut, Fut: Future<Output = ()>, D: Default, S: DeserializeOwned {
+            let (receiver, mut stream) = socket.split();
+            let connection = Connection::with_meta(conn_id.to_string(), receiver, meta);
+            let (membership, _) = Self::enter(broadcaster, room_id, connection.clone(), None).await?;
+
+            let session = Session {
+                broadcaster: Arc::clone(broadcaster),
+                room_id: room_id.to_string(),
+                connection
+            };
+
+            let stopped = crate::writer_failure(session.connection.failure.clone());
+            let rate_limit = broadcaster.read().await.rate_limit.clone();
+            let mut bucket = Bucket::new();
+
+            tokio::pin!(stopped);
+
+            loop {
+                // stop as soon as the connection is closed from our side too, such as by the heartbeat, even if the client never answers it.
+                let message = tokio::select! {
+                    message = stream.next() => message,
+                    _ = &mut stopped => break
+                };
+
+                let incoming = match message {
+                    Some(Ok(Message::Item(item))) => item,
+                    Some(Ok(Message::Pong(payload))) => {
+                        session.connection.record_pong(&payload);
+
+                        continue;
+                    },
+                    // pings are answered by the socket itself while it's read and a message which can't be decoded is skipped.
+                    Some(Ok(Message::Ping(_))) | Some(Err(axum_typed_websockets::Error::Codec(_))) => continue,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break
+                };
+
+                let limit = rate_limit.get().filter(|limit| !bucket.take(limit.per_second, limit.burst));
+
+                match limit.map(|limit| limit.action) {
+                    None => handler(session.clone(), incoming).await,
+                    Some(RateAction::Drop) => (),
+                    Some(RateAction::Warn(warning)) => {
+                        let _ = session.connection.enqueue(Message::Item(warning()));
+                    },
+                    Some(RateAction::Close(close_frame)) => {
+                        let close_timeout = broadcaster.read().await.config.get().close_timeout;
+                        let _ = session.connection.clone().close(close_frame, close_timeout).await;
+
+                        break;
+                    }
+                }
+            }
+
+            membership.release().await;
+
+            Ok(())
+        }
+
+        /// put the connection into the room with given id, creating the room if it doesn't exist, and give the membership of it.
+        async fn enter(broadcaster: &Arc<RwLock<Self>>, room_id: &str, connection: Connection<T, S, M>, last_seen: Option<u64>) -> Result<(Membership<T, S, M, D>, Resume)> where D: Default {
+            let permit = {
+                let this = broadcaster.read().await;
+                let quota = this.config.get().quota;
+                let key = this.quota_key.get().0.and_then(|key| key(&connection));
+
+                this.quotas.acquire(key, quota.total, quota.per_key)
+            };
+
+            let permit = match permit {
+                Ok(permit) => permit,
+                Err(error) => {
+                    let config = broadcaster.read().await.config.get();
+                    let _ = connection.close(config.quota.close_frame, config.close_timeout).await;
+
+                    return Err(error);
+                }
+            };
+
+            // the clones of the connection in the rooms share the permit, so it's counted as long as it's in any of them, even after the membership is detached.
+            let _ = connection.permit.set(permit);
+
+            let resume = loop {
+                let (handle, mut room) = loop {
+                    let handle = {
+                        let mut this = broadcaster.write().await;
+
+                        this.spawn_heartbeat(Arc::downgrade(broadcaster));
+                        this.spawn_reaper(Arc::downgrade(broadcaster));
+                        this.handle_room(room_id)
+                    };
+
+                    let room = Arc::clone(&handle).write_owned().await;
+
+                    // the room can be removed while we wait for it's lock, than it has to be created again.
+                    if !room.removed {
+                        break (handle, room);
+                    }
+                };
+
+                let (promoted, timeout) = match (room.admit(connection.clone(), last_seen), room.config.get().capacity_policy) {
+                    (Err(Error::RoomFull(_)), CapacityPolicy::Waitlist(timeout)) => (room.wait(connection.clone(), last_seen), timeout),
+                    (admission, _) => break admission?
+                };
+
+                drop(room);
+
+                // if this future is dropped while waiting, such as when the client disconnects, the waiter is taken off the waitlist by the drop of it.
+                let mut waiting = Waiting {
+                    room: handle,
+                    id: connection.id.clone(),
+                    failure: connection.failure.clone(),
+                    promoted,
+                    settled: false
+                };
+
+                match tokio::time::timeout(timeout, &mut waiting.promoted).await {
+                    Ok(Ok(admission)) => {
+                        waiting.settled = true;
+
+                        break admission?;
+                    },
+                    // the room is removed while waiting, it's created again.
+                    Ok(Err(_)) => {
+                        waiting.settled = true;
+
+                        continue;
+                    },
+                    Err(_) => match waiting.leave().await {
+                        // it can be promoted right before the timeout.
+                        Some(admission) => break admission?,
+                        None => return Err(Error::RoomFull(room_id.to_string()))
+                    }
+                }
+            };
+
+            let membership = Membership {
+                broadcaster: Arc::clone(broadcaster),
+                room_id: room_id.to_string(),
+                connection,
+                released: false
+            };
+
+            Ok((membership, resume))
+        }
+
+        /// start the heartbeat in the background if it's set in the config and it's not running already. `.handle()` and `.serve()` start it by themselves, call it if you add connections in another way. Turning it off in the config stops it at it's next tick.
+        pub async fn start_heartbeat(broadcaster: &Arc<RwLock<Self>>) {
+            broadcaster.write().await.spawn_heartbeat(Arc::downgrade(broadcaster));
+        }
+
+        fn spawn_heartbeat(&mut self, this: Weak<RwLock<Self>>) {
+            let running = self.heartbeat.as_ref().is_some_and(|heartbeat| !heartbeat.is_finished());
+
+            if running || self.config.get().heartbeat.is_none() {
+                return;
+            }
+
+            self.heartbeat = Some(tokio::spawn(heartbeat(this, self.config.clone())).abort_handle());
+        }
+
+        /// start the reaper in the background if it's set in the config and it's not running already. `.handle()` and `.serve()` start it by themselves, call it if you create rooms in another way, such as with `.create_room()`. Turning it off in the config stops it at it's next tick.
+        pub async fn start_reaper(broadcaster: &Arc<RwLock<Self>>) {
+            broadcaster.write().await.spawn_reaper(Arc::downgrade(broadcaster));
+        }
+
+        fn spawn_reaper(&mut self, this: Weak<RwLock<Self>>) {
+            let running = self.reaper.as_ref().is_some_and(|reaper| !reaper.is_finished());
+
+            if running || self.config.get().reaper.is_none() {
+                return;
+            }
+
+            self.reaper = Some(tokio::spawn(reaper(this, self.config.clone())).abort_handle());
+        }
+
+        /// create new broadcaster:
+        pub fn new() -> Arc<RwLock<Self>> {
+            Arc::new(RwLock::new(Self::default()))
+        }
+
+        /// create new broadcaster with given settings:
+        pub fn with_config(config: Config) -> Arc<RwLock<Self>> {
+            Arc::new(RwLock::new(Self {
+                rooms: HashMap::new(),
+                index: Index::default(),
+                config: Shared::new(config),
+                presence: Shared::default(),
+                admission: Shared::default(),
+                quota_key: Shared::default(),
+                rate_limit: Shared::default(),
+                quotas: Quotas::default(),
+                heartbeat: None,
+                reaper: None
+            }))
+        }
+
+        /// get a copy of the current settings of broadcaster.
+        pub fn config(&self) -> Config {
+            self.config.get()
+        }
+
+        /// replace the settings of broadcaster. The new settings are applied to the existing rooms too.
+        pub fn set_config(&self, config: Config) {
+            self.config.set(config);
+        }
+
+        /// limit the data messages which `.serve()` reads from each connection, the connections which are already served are limited too. `None` removes the limit.
+        pub fn set_rate_limit(&self, limit: Option<RateLimit<T>>) {
+            self.rate_limit.set(limit);
+        }
+
+        /// announce the connections which join, leave or update their metadata to the other members of each room they're in, with the message which is built by given format. `None` stops announcing.
+        pub fn set_presence(&self, format: Option<PresenceFormat<T, M>>) {
+            self.presence.set(Announcer(format));
+        }
+
+        /// count the connections by the key which given callback gives, for the `per_key` quota of the config. `None` counts them only in the total.
+        pub fn set_quota_key(&self, key: Option<QuotaKey<T, S, M>>) {
+            self.quota_key.set(Keyer(key));
+        }
+
+        /// how many connections the broadcaster took through `.handle()` and `.serve()`, which are not dropped yet.
+        pub fn total_connections(&self) -> usize {
+            self.quotas.total()
+        }
+
+        /// how many of those connections have given quota key.
+        pub fn connections_by_key(&self, key: &str) -> usize {
+            self.quotas.count(key)
+        }
+
+        /// check every connection which joins a room with given callback, before it's put into the room. `None` lets everyone in.
+        pub fn set_admission(&self, check: Option<Admission<T, S, M, D>>) {
+            self.admission.set(Gate(check));
+        }
+
+        /// check if a room with given id exist and if it's not create one. It returns the handle of the room, lock it for using the room.
+        pub fn handle_room(&mut self, id: &str) -> RoomHandle<T, S, M, D> where D: Default {
+            self.room_entry(id, false, RoomState::default)
+        }
+
+        /// create a room with given state. If a room with that id already exists, `Error::DuplicateRoom` is returned and it's state is not touched. The room isn't removed when it's last member leaves, only by `.remove_room()`, `.remove_empty_rooms()` or the reaper when it expires.
+        pub fn create_room(&mut self, id: &str, state: RoomState<D>) -> Result<RoomHandle<T, S, M, D>> {
+            match self.rooms.contains_key(id) {
+                true => Err(Error::DuplicateRoom(id.to_string())),
+                false => Ok(self.room_entry(id, true, || state))
+            }
+        }
+
+        /// get the room with given id, creating it with the state which is given by the closure if it doesn't exist.
+        fn room_entry<F>(&mut self, id: &str, persistent: bool, state: F) -> RoomHandle<T, S, M, D> where F: FnOnce() -> RoomState<D> {
+            let mut created = false;
+
+            let room = self.rooms.entry(id.to_string()).or_insert_with(|| Arc::new_cyclic(|this| {
+                created = true;
+
+                RwLock::new(Room {
+                    id: id.to_string(),
+                    connections: HashMap::new(),
+                    count: 0,
+                    index: self.index.clone(),
+                    config: self.config.clone(),
+                    presence: self.presence.clone(),
+                    admission: self.admission.clone(),
+                    this: this.clone(),
+                    removed: false,
+                    persistent,
+                    state: state(),
+                    backpressure: None,
+                    empty_since: Some(Instant::now()),
+                    waitlist: VecDeque::new(),
+                    history: Backlog::default(),
+                    order: Mutex::new(())
+                })
+            }));
+
+            let room = Arc::clone(room);
+
+            if created {
+                crate::emit(&self.config.get().on_event, || Event::RoomCreated { room: id.to_string() });
+            }
+
+            room
+        }
+
+        /// iterates through every room and does something with them immutably. You cannot mutate anything inside of it, even rooms and not captured variables.
+        ///
+        /// ```rust
+        ///
+        /// use axum_wsb::typed::Broadcaster;
+        /// use tokio::sync::RwLock;
+        /// use std::sync::Arc;
+        ///
+        /// fn main () {
+        ///     let receivers: Arc<RwLock<Broadcaster<String, String>>> = Broadcaster::new();
+        ///
+        ///     async {
+        ///         receivers.read().await.each_room_immut(|room| println!("hello, {}. guest!", room.id)).await;
+        ///     };
+        ///
+        /// }
+        ///
+        ///
+        /// ```
+        pub async fn each_room_immut<F>(&self, f: F) where F: Fn(&Room<T, S, M, D>) {
+            for room in self.rooms.values() {
+                f(&*room.read().await);
+            }
+        }
+
+        /// iterates through every room and does something with them immutably. You cannot mutate rooms itself but can mutate captured variables.
+        ///
+        /// ```rust
+        ///
+        /// use axum_wsb::typed::Broadcaster;
+        /// use tokio::sync::RwLock;
+        /// use std::sync::Arc;
+        ///
+        /// fn main () {
+        ///     let receivers: Arc<RwLock<Broadcaster<String, String>>> = Broadcaster::new();
+        ///
+        ///     let mut num = 0;
+        ///
+        ///     async {
+        ///         receivers.read().await.each_room(|room| {
+        ///             num = num + 1;
+        ///         }).await;
+        ///     };
+        ///
+        ///
+        ///     println!("here is number: {}", num)
+        /// }
+        ///
+        ///
+        /// ```
+        pub async fn each_room<F>(&self, mut f: F) where F: FnMut(&Room<T, S, M, D>) {
+            for room in self.rooms.values() {
+                f(&*room.read().await);
+            }
+        }
+
+        /// iterates through every room and does something with them mutably. You can mutate everything belong to it. But warning, for now, you cannot send messages to client from it right now and until async closures will be stable probably we're not be able to do it. Because of that, we're not able to give examples for that.
+        pub async fn each_room_mut<F>(&self, mut f: F) where F: FnMut(&mut Room<T, S, M, D>) {
+            for room in self.rooms.values() {
+                f(&mut *room.write().await);
+            }
+        }
+
+        /// Get the handle of the room with given id. If the room doesn't exist, `Error::UnknownRoom` is returned. Get it in it's own statement, so the broadcaster is unlocked before you lock the room:
+        ///
+        /// ```rust
+        ///
+        /// use axum_wsb::typed::Broadcaster;
+        /// use tokio::sync::RwLock;
+        /// use std::sync::Arc;
+        ///
+        /// fn main () {
+        ///     let receivers: Arc<RwLock<Broadcaster<String, String>>> = Broadcaster::new();
+        ///
+        ///     async {
+        ///         let room = receivers.read().await.room(&"room".to_string());
+        ///
+        ///         if let Ok(room) = room {
+        ///             room.read().await.broadcast(&"hello".to_string()).await;
+        ///         }
+        ///     };
+        /// }
+        ///
+        /// ```
+        pub fn room(&self, id: &String) -> Result<RoomHandle<T, S, M, D>> {
+            self.rooms.get(id).cloned().ok_or_else(|| Error::UnknownRoom(id.clone()))
+        }
+
+        /// iterate through the handles of the rooms, in no particular order.
+        pub fn rooms(&self) -> impl Iterator<Item = &RoomHandle<T, S, M, D>> {
+            self.rooms.values()
+        }
+
+        /// check if a room with given id exist and wrap it's handle in an option.
+        pub fn check_room(&self, id: &String) -> Option<RoomHandle<T, S, M, D>> {
+            self.rooms.get(id).cloned()
+        }
+
+        /// only check if a room is exist and return a bool.
+        pub fn check(&self, id: &String) -> bool {
+            self.rooms.contains_key(id)
+        }
+
+        /// it removes a room with given id and closes all the connections inside of it with the close frame in the config. The broadcaster is only locked while the room is taken out, so the close handshakes don't hold up the other rooms:
+        pub async fn remove_room(broadcaster: &Arc<RwLock<Self>>, id: &String) -> Result<CloseReport> {
+            let (connections, config) = {
+                let mut this = broadcaster.write().await;
+                let room = this.rooms.remove(id).ok_or_else(|| Error::UnknownRoom(id.clone()))?;
+                let mut room = room.write().await;
+
+                room.removed = true;
+                room.waitlist.clear();
+
+                (room.take_where(|_| true), this.config.get())
+            };
+
+            crate::emit(&config.on_event, || Event::RoomRemoved { room: id.clone() });
+
+            Ok(close_all(connections, config.close_frame, config.close_timeout).await)
+        }
+
+        /// remove the room with given id if it has no connections.
+        async fn remove_if_empty(&mut self, id: &String) {
+            let Some(room) = self.check_room(id) else {
+                return;
+            };
+
+            let mut room = room.write().await;
+
+            if room.is_empty() {
+                room.removed = true;
+                room.waitlist.clear();
+                self.rooms.remove(id);
+
+                crate::emit(&self.config.get().on_event, || Event::RoomRemoved { room: id.clone() });
+            }
+        }
+
+        /// it removes all empty rooms. Rooms which are locked at that moment are in use, so they're kept.
+        pub fn remove_empty_rooms(&mut self) {
+            let hook = self.config.get().on_event;
+
+            self.rooms.retain(|id, room| match room.try_write() {
+                Ok(mut room) if room.is_empty() => {
+                    room.removed = true;
+                    room.waitlist.clear();
+
+                    crate::emit(&hook, || Event::RoomRemoved { room: id.clone() });
+
+                    false
+                },
+                _ => true
+            });
+        }
+
+        /// Removes the connections with given id from every room they're in and gives them back, each of them once. Use `.close()` on the returned connections for closing them gracefully, dropping them only lets their writer tasks deliver the queued messages and than drop the receivers without any close frame.
+        pub async fn remove_connection(&self, id: &String) -> Vec<Connection<T, S, M>> {
+            let mut removed: Vec<Connection<T, S, M>> = vec![];
+
+            for room_id in self.index.rooms_of(id) {
+                let Some(room) = self.check_room(&room_id) else {
+                    continue;
+                };
+
+                for connection in room.write().await.take(id) {
+                    if !removed.iter().any(|removed| removed.is(&connection)) {
+                        removed.push(connection);
+                    }
+                }
+            }
+
+            removed
+        }
+
+        /// join the connections with given id to the room with given id too, creating the room if it doesn't exist. They're gathered from every room they're in, so connections which share an id but are in different rooms all join. They keep their sockets, so they get the messages of every room they're in.
+        pub async fn join(&mut self, conn_id: &str, room_id: &str) -> Result<()> where D: Default {
+            let connections = self.find(conn_id).await;
+
+            if connections.is_empty() {
+                return Err(Error::UnknownConnection(conn_id.to_string()));
+            }
+
+            let room = self.handle_room(room_id);
+            let mut room = room.write().await;
+
+            for (_, _, connection) in connections {
+                room.join(connection)?;
+            }
+
+            Ok(())
+        }
+
+        /// take the connections with given id out of the room with given id only and give them back. They stay in their other rooms, if they're in none of them anymore, close the returned connections.
+        pub async fn leave(&self, conn_id: &String, room_id: &String) -> Result<Vec<Connection<T, S, M>>> {
+            let room = self.room(room_id)?;
+            let connections = room.write().await.take(conn_id);
+
+            match connections.is_empty() {
+                true => Err(Error::UnknownConnection(conn_id.clone())),
+                false => Ok(connections)
+            }
+        }
+
+        /// ids of the rooms which the connection with given id is in, in no particular order.
+        pub fn rooms_of(&self, conn_id: &str) -> Vec<String> {
+            self.index.rooms_of(conn_id)
+        }
+
+        /// change the metadata of the connections with given id and announce it to the other members of every room they're in. If there is no such connection, `Error::UnknownConnection` is returned.
+        pub async fn update_meta(&self, conn_id: &str, meta: M) -> Result<()> {
+            let meta = Arc::new(meta);
+            let mut updated = false;
+
+            for room_id in self.index.rooms_of(conn_id) {
+                let Some(room) = self.rooms.get(&room_id) else {
+                    continue;
+                };
+
+                let room = room.read().await;
+
+                for connection in room.connections.get(conn_id).into_iter().flatten() {
+                    connection.meta.set(Arc::clone(&meta));
+                    room.announce(PresenceKind::Updated, connection);
+                    updated = true;
+                }
+            }
+
+            match updated {
+                true => Ok(()),
+                false => Err(Error::UnknownConnection(conn_id.to_string()))
+            }
+        }
+
+        /// send the message to the connections with given id, whatever room they're in. Connections which share an id all get it and a connection which is in many rooms gets it once. It waits for the queues by the backpressure policy of the config, a dropped message is returned as `Error::QueueFull`. The broadcaster is only locked while the connections are found.
+        pub async fn send_to(broadcaster: &Arc<RwLock<Self>>, conn_id: &str, message: &T) -> Result<()> where T: Clone {
+            Self::deliver_to(broadcaster, [conn_id], Message::Item(message.clone())).await.into_result()
+        }
+
+        /// send the message to the connections with given ids, whatever room they're in, and report which of them took it. Ids which have no connection are reported in `failed`.
+        pub async fn send_to_many<I>(broadcaster: &Arc<RwLock<Self>>, ids: I, message: &T) -> DeliveryReport where I: IntoIterator, I::Item: AsRef<str>, T: Clone {
+            Self::deliver_to(broadcaster, ids, Message::Item(message.clone())).await
+        }
+
+        /// find the connections with given id in every room they're in, with the room they're found in. A connection which is in many rooms is given once.
+        async fn find(&self, conn_id: &str) -> Vec<Target<T, S, M, D>> {
+            let mut found: Vec<Target<T, S, M, D>> = vec![];
+            let mut seen = HashSet::new();
+
+            for room_id in self.index.rooms_of(conn_id) {
+                let Some(room) = self.rooms.get(&room_id) else {
+                    continue;
+                };
+
+                for connection in room.read().await.connections.get(conn_id).into_iter().flatten() {
+                    if seen.insert(Arc::as_ptr(&connection.liveness) as usize) {
+                        found.push((room_id.clone(), Arc::downgrade(room), connection.clone()));
+                    }
+                }
+            }
+
+            found
+        }
+
+        /// push the message into the queues of the connections with given ids concurrently, by the backpressure policy of the config, and report what happened for each of them. An id which has no connection is reported in `failed` with `Error::UnknownConnection`.
+        async fn deliver_to<I>(broadcaster: &Arc<RwLock<Self>>, ids: I, message: Message<T>) -> DeliveryReport where I: IntoIterator, I::Item: AsRef<str>, T: Clone {
+            // the broadcaster is only locked while the connections are found, it's unlocked before waiting for their queues.
+            let (targets, unknown, config) = {
+                let this = broadcaster.read().await;
+                let mut targets: Vec<Target<T, S, M, D>> = vec![];
+                let mut unknown = vec![];
+                let mut seen = HashSet::new();
+
+                for id in ids {
+                    let id = id.as_ref();
+
+                    // connections are found by their id, so a repeated id is the only way of finding one twice.
+                    if !seen.insert(id.to_string()) {
+                        continue;
+                    }
+
+                    let found = this.find(id).await;
+
+                    if found.is_empty() {
+                        unknown.push((id.to_string(), Error::UnknownConnection(id.to_string())));
+                    }
+
+                    targets.extend(found);
+                }
+
+                (targets, unknown, this.config.get())
+            };
+
+            let mut report = fan_out(queue_all(targets.iter().map(|(room_id, room, connection)| (room, room_id.as_str(), connection)), message, &config.backpressure, &config), &config).await;
+
+            report.failed.extend(unknown);
+
+            report
+        }
+    }
+
+    /// what the handler of `.serve()` gets with each message: the broadcaster, id of the room which the connection joined and the connection itself, for answering it.
+    #[derive(Debug)]
+    pub struct Session<T, S, M = (), D = ()> {
+        pub broadcaster: Arc<RwLock<Broadcaster<T, S, M, D>>>,
+        pub room_id: String,
+        pub connection: Connection<T, S, M>
+    }
+
+    impl<T, S, M, D> Clone for Session<T, S, M, D> {
+        fn clone(&self) -> Self {
+            Self {
+                broadcaster: Arc::clone(&self.broadcaster),
+                room_id: self.room_id.clone(),
+                connection: self.connection.clone()
+            }
+        }
+    }
+
+    impl<T, S, M, D> Session<T, S, M, D> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        /// get the handle of the room which the connection joined.
+        pub async fn room(&self) -> Result<RoomHandle<T, S, M, D>> {
+            self.broadcaster.read().await.room(&self.room_id)
+        }
+    }
+
+    /// membership of a connection, which is returned from `Broadcaster::handle()`. It can be used like the broadcaster itself. When it's dropped, such as when the handler of the socket returns or panics,
+    /// the connection is removed from every room it's in and the rooms which become empty are removed too, unless the reaper has an `empty_ttl` or they're created by `.create_room()`. A connection which replaced it with the same id is not touched.
+    #[must_use = "the connection is removed from it's rooms as soon as the membership is dropped"]
+    #[derive(Debug)]
+    pub struct Membership<T, S, M = (), D = ()> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        broadcaster: Arc<RwLock<Broadcaster<T, S, M, D>>>,
+        room_id: String,
+        connection: Connection<T, S, M>,
+        released: bool
+    }
+
+    impl<T, S, M, D> Membership<T, S, M, D> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        /// id of the connection.
+        pub fn id(&self) -> &str {
+            &self.connection.id
+        }
+
+        /// the connection itself, such as for recording the pongs which your read loop receives.
+        pub fn connection(&self) -> &Connection<T, S, M> {
+            &self.connection
+        }
+
+        /// id of the room which the connection joined with `.handle()`.
+        pub fn room_id(&self) -> &str {
+            &self.room_id
+        }
+
+        /// remove the connection from it's rooms now and wait until it's done, instead of leaving it to the drop.
+        pub async fn release(mut self) {
+            self.released = true;
+
+            release(&self.broadcaster, &self.room_id, &self.connection.id, &self.connection.failure).await;
+        }
+
+        /// give up the membership without removing the connection, it stays in it's rooms until you remove it by yourself.
+        pub fn detach(mut self) -> Arc<RwLock<Broadcaster<T, S, M, D>>> {
+            self.released = true;
+
+            Arc::clone(&self.broadcaster)
+        }
+    }
+
+    impl<T, S, M, D> Deref for Membership<T, S, M, D> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        type Target = Arc<RwLock<Broadcaster<T, S, M, D>>>;
+
+        fn deref(&self) -> &Self::Target {
+            &self.broadcaster
+        }
+    }
+
+    impl<T, S, M, D> Drop for Membership<T, S, M, D> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        fn drop(&mut self) {
+            if self.released {
+                return;
+            }
+
+            // locks can't be waited in drop, so the cleanup runs in the background. If there is no runtime anymore, there is nothing left to clean either.
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let broadcaster = Arc::clone(&self.broadcaster);
+                let room_id = self.room_id.clone();
+                let id = self.connection.id.clone();
+                let failure = self.connection.failure.clone();
+
+                runtime.spawn(async move {
+                    release(&broadcaster, &room_id, &id, &failure).await;
+                });
+            }
+        }
+    }
+
+    /// a connection's place on the waitlist of a room, while `.handle()` waits for it to be promoted. The waiter keeps a clone of the connection, so if it's dropped before it's promoted, the waiter is taken off the waitlist for not keeping the socket and it's quota alive. If it's promoted in the meantime, it's seat is freed.
+    struct Waiting<T, S, M, D> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        room: RoomHandle<T, S, M, D>,
+        id: String,
+        failure: watch::Receiver<Option<Error>>,
+        promoted: oneshot::Receiver<Result<Resume>>,
+        settled: bool
+    }
+
+    impl<T, S, M, D> Waiting<T, S, M, D> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        /// stop waiting and take the waiter off the waitlist. The admission is returned if it's promoted right before that.
+        async fn leave(mut self) -> Option<Result<Resume>> {
+            self.settled = true;
+            self.promoted.close();
+
+            if let Ok(admission) = self.promoted.try_recv() {
+                return Some(admission);
+            }
+
+            self.room.write().await.waitlist.retain(|waiter| !waiter.promoted.is_closed());
+
+            None
+        }
+    }
+
+    impl<T, S, M, D> Drop for Waiting<T, S, M, D> where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        fn drop(&mut self) {
+            if self.settled {
+                return;
+            }
+
+            self.promoted.close();
+
+            let admitted = matches!(self.promoted.try_recv(), Ok(Ok(_)));
+
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let room = Arc::clone(&self.room);
+                let id = self.id.clone();
+                let failure = self.failure.clone();
+
+                runtime.spawn(async move {
+                    let mut room = room.write().await;
+
+                    if admitted {
+                        room.evict(&id, &failure);
+                    }
+
+                    room.waitlist.retain(|waiter| !waiter.promoted.is_closed());
+                });
+            }
+        }
+    }
+
+    /// remove the connection which has given writer from every room it's in, than remove the rooms which became empty. If the reaper has an `empty_ttl`, emptied rooms are left to it, and rooms which are created by `.create_room()` are always kept.
+    async fn release<T, S, M, D>(broadcaster: &Arc<RwLock<Broadcaster<T, S, M, D>>>, room_id: &String, id: &String, failure: &watch::Receiver<Option<Error>>) where T: Display + Serialize + Send + 'static, S: Display + Serialize + 'static, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        let (rooms, prune): (Vec<RoomHandle<T, S, M, D>>, bool) = {
+            let broadcaster = broadcaster.read().await;
+            let prune = !broadcaster.config.get().reaper.is_some_and(|reaper| reaper.empty_ttl.is_some());
+            let mut room_ids = broadcaster.index.rooms_of(id);
+
+            if !room_ids.contains(room_id) {
+                room_ids.push(room_id.clone());
+            }
+
+            (room_ids.iter().filter_map(|room_id| broadcaster.check_room(room_id)).collect(), prune)
+        };
+
+        let mut emptied = vec![];
+
+        for room in rooms {
+            let mut room = room.write().await;
+
+            room.evict(id, failure);
+
+            if prune && !room.persistent && room.is_empty() {
+                emptied.push(room.id.clone());
+            }
+        }
+
+        if emptied.is_empty() {
+            return;
+        }
+
+        let mut broadcaster = broadcaster.write().await;
+
+        for room_id in emptied {
+            broadcaster.remove_if_empty(&room_id).await;
+        }
+    }
+
+    impl<T, S, M, D> Default for Broadcaster<T, S, M, D> {
+        fn default() -> Self {
+            Self {
+                rooms: HashMap::new(),
+                index: Index::default(),
+                config: Shared::default(),
+                presence: Shared::default(),
+                admission: Shared::default(),
+                quota_key: Shared::default(),
+                rate_limit: Shared::default(),
+                quotas: Quotas::default(),
+                heartbeat: None,
+                reaper: None
+            }
+        }
+    }
+}
+
+pub mod normal {
+    use axum_8_4::{body::Bytes, extract::ws::{CloseFrame, Message, Utf8Bytes, WebSocket}};
+    use std::{collections::{HashMap, HashSet, VecDeque}, fmt, future::Future, ops::Deref, sync::{Arc, Mutex, OnceLock, PoisonError, Weak}, time::{Duration, SystemTime}};
+    use tokio::{sync::{oneshot, watch, RwLock}, task::AbortHandle, time::Instant};
+    use futures_util::{Sink, future::join_all, stream::{FuturesUnordered, SplitSink, SplitStream, StreamExt}};
+    use crate::{Backlog, Bucket, CapacityPolicy, CloseReport, DeliveryReport, Error, Event, EventHook, EvictHook, Eviction, History, Index, Liveness, Outbox, Overflow, Permit, PresenceEvent, PresenceKind, Queued, Quotas, Result, Resume, RoomState, Shared, DEFAULT_CLOSE_TIMEOUT, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_QUEUE_CAPACITY, DEFAULT_REAPER_INTERVAL, DEFAULT_SEND_TIMEOUT};
+
+    /// main broadcaster for normal api. Rooms are kept by their ids and the ids of connections are indexed, so finding a room or a connection doesn't depend on how many of them exist.
+    #[derive(Debug)]
+    pub struct Broadcaster<M = (), D = ()> {
+        rooms: HashMap<String, RoomHandle<M, D>>,
+        index: Index,
+        config: Shared<Config>,
+        presence: Shared<Announcer<M>>,
+        admission: Shared<Gate<M, D>>,
+        quota_key: Shared<Keyer<M>>,
+        rate_limit: Shared<Option<RateLimit>>,
+        quotas: Quotas,
+        heartbeat: Option<AbortHandle>,
+        reaper: Option<AbortHandle>
+    }
+
+    /// a room which can be locked by itself. Broadcaster only needs to be locked for getting it, so sending to a room never waits for the others.
+    pub type RoomHandle<M = (), D = ()> = Arc<RwLock<Room<M, D>>>;
+
+    /// a reference to a room which doesn't keep it alive.
+    type WeakRoom<M, D> = Weak<RwLock<Room<M, D>>>;
+
+    /// a connection which is found by it's id, with the id of the room it's found in and a weak reference to that room.
+    type Target<M, D> = (String, WeakRoom<M, D>, Connection<M>);
+
+    /// a connection which a message is queued for, with the room it's in and what happened to the message.
+    type Queueing<'a, M, D> = (&'a WeakRoom<M, D>, &'a str, &'a Connection<M>, Result<Queued>);
+
+    /// connections which are evicted from a room, with the errors that evicted them.
+    type Evictions = Vec<(String, watch::Receiver<Option<Error>>, Error)>;
+
+    /// settings of a broadcaster. They are shared with every room of it.
+    #[derive(Clone)]
+    pub struct Config {
+        /// how long the closing methods wait for a connection to flush it's pending messages and the close frame.
+        pub close_timeout: Duration,
+        /// the close frame which is sent when the broadcaster closes connections by itself, such as in `Broadcaster::remove_room()`.
+        pub close_frame: Option<CloseFrame>,
+        /// how long broadcasting methods wait for a place in the outbound queue of each connection. Connections are waited concurrently, so a broadcast takes at most that long. Zero means not waiting at all.
+        pub send_timeout: Duration,
+        /// what broadcasting methods do when the outbound queue of a connection is full. Rooms can override it with `.set_backpressure()`.
+        pub backpressure: Backpressure,
+        /// what to do when a connection joins a room with an id which is already in there.
+        pub duplicate_policy: DuplicatePolicy,
+        /// what `.handle()` does when the room is at it's capacity.
+        pub capacity_policy: CapacityPolicy,
+        /// limits of the connections which the broadcaster takes. It's unlimited by default.
+        pub quota: Quota,
+        /// remove a connection from it's room as soon as writing to it's socket fails. It's off by default.
+        pub evict_on_error: bool,
+        /// remove a connection from it's room when a broadcast to it times out. It's off by default.
+        pub evict_on_timeout: bool,
+        /// called after a connection is evicted, if `evict_on_error` or `evict_on_timeout` is on.
+        pub on_evict: Option<EvictHook>,
+        /// called with every event of the broadcaster, such as rooms being created or connections leaving. Rooms or the broadcaster can be locked while it's called, so spawn a task if you need to lock them.
+        pub on_event: Option<EventHook>,
+        /// ping the connections in the background and close the ones which stop answering. It's off by default.
+        pub heartbeat: Option<Heartbeat>,
+        /// keep the recent broadcasts of each room and replay them to the connections which join later. It's off by default.
+        pub history: Option<History>,
+        /// remove the rooms which are left empty for too long and close the expired ones in the background. It's off by default.
+        pub reaper: Option<Reaper>
+    }
+
+    impl Default for Config {
+        fn default() -> Self {
+            Self {
+                close_timeout: DEFAULT_CLOSE_TIMEOUT,
+                close_frame: None,
+                send_timeout: DEFAULT_SEND_TIMEOUT,
+                backpressure: Backpressure::Wait,
+                duplicate_policy: DuplicatePolicy::Reject,
+                capacity_policy: CapacityPolicy::Reject,
+                quota: Quota::default(),
+                evict_on_error: false,
+                evict_on_timeout: false,
+                on_evict: None,
+                on_event: None,
+                heartbeat: None,
+                history: None,
+                reaper: None
+            }
+        }
+    }
+
+    impl fmt::Debug for Config {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Config")
+                .field("close_timeout", &self.close_timeout)
+                .field("close_frame", &self.close_frame)
+                .field("send_timeout", &self.send_timeout)
+                .field("backpressure", &self.backpressure)
+                .field("duplicate_policy", &self.duplicate_policy)
+                .field("capacity_policy", &self.capacity_policy)
+                .field("quota", &self.quota)
+                .field("evict_on_error", &self.evict_on_error)
+                .field("evict_on_timeout", &self.evict_on_timeout)
+                .field("on_evict", &self.on_evict.is_some())
+                .field("on_event", &self.on_event.is_some())
+                .field("heartbeat", &self.heartbeat)
+                .field("history", &self.history)
+                .field("reaper", &self.reaper)
+                .finish()
+        }
+    }
+
+    /// settings of the heartbeat. Every `interval`, each connection is pinged and the ones which didn't answer `max_missed` pings in a row are closed with `close_frame`.
+    #[derive(Debug, Clone)]
+    pub struct Heartbeat {
+        pub interval: Duration,
+        /// zero is treated as one.
+        pub max_missed: u32,
+        pub close_frame: Option<CloseFrame>
+    }
+
+    impl Default for Heartbeat {
+        fn default() -> Self {
+            Self {
+                interval: DEFAULT_HEARTBEAT_INTERVAL,
+                max_missed: 3,
+                close_frame: None
+            }
+        }
+    }
+
+    /// limits of the connections which a broadcaster takes through `.handle()` and `.serve()`. A connection over them fails with `Error::QuotaExceeded` and it's closed with `close_frame`.
+    #[derive(Debug, Clone)]
+    pub struct Quota {
+        /// how many connections the broadcaster takes at most, `None` is unlimited.
+        pub total: Option<usize>,
+        /// how many connections with the same key the broadcaster takes at most, keys are given by `Broadcaster::set_quota_key()`. `None` is unlimited.
+        pub per_key: Option<usize>,
+        pub close_frame: Option<CloseFrame>
+    }
+
+    impl Default for Quota {
+        fn default() -> Self {
+            Self {
+                total: None,
+                per_key: None,
+                close_frame: Some(CloseFrame { code: 1008, reason: "too many connections".into() })
+            }
+        }
+    }
+
+    /// settings of the reaper. Every `interval`, rooms which are empty for longer than `empty_ttl` are removed and the ones whose `expires_at` in their state has passed are removed after their connections are closed with `close_frame`. Rooms which are created by `.create_room()` or have an `expires_at` aren't removed for being empty.
+    #[derive(Debug, Clone)]
+    pub struct Reaper {
+        pub interval: Duration,
+        /// `None` keeps the empty rooms.
+        pub empty_ttl: Option<Duration>,
+        pub close_frame: Option<CloseFrame>
+    }
+
+    impl Default for Reaper {
+        fn default() -> Self {
+            Self {
+                interval: DEFAULT_REAPER_INTERVAL,
+                empty_ttl: Some(Duration::from_secs(60)),
+                close_frame: None
+            }
+        }
+    }
+
+    /// what broadcasting methods do when the outbound queue of a connection is full, which means the client reads slower than the room talks.
+    #[derive(Debug, Clone, Default)]
+    pub enum Backpressure {
+        /// wait for a place in the queue at most for the `send_timeout` of the config, than fail with `Error::Timeout`. It's the default.
+        #[default]
+        Wait,
+        /// drop the oldest message in the queue for making place to the new one.
+        DropOldest,
+        /// drop the new message, the connection is reported in `dropped` of the `DeliveryReport`.
+        DropNewest,
+        /// drop the pending data messages and queue the new one, so the client skips to the latest message. Pings and pongs are kept.
+        Coalesce,
+        /// drop the pending messages and close the connection with given close frame, it's reported with `Error::QueueFull`.
+        Disconnect(Option<CloseFrame>)
+    }
+
+    /// builds the message which announces a presence event to the other members of the room. Return `None` for not announcing it.
+    pub type PresenceFormat<M = ()> = Arc<dyn Fn(&PresenceEvent<'_, M>) -> Option<Utf8Bytes> + Send + Sync>;
+
+    /// the presence format which a broadcaster shares with all of it's rooms.
+    struct Announcer<M>(Option<PresenceFormat<M>>);
+
+    impl<M> Clone for Announcer<M> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
+        }
+    }
+
+    impl<M> Default for Announcer<M> {
+        fn default() -> Self {
+            Self(None)
+        }
+    }
+
+    impl<M> fmt::Debug for Announcer<M> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("Announcer").field(&self.0.is_some()).finish()
+        }
+    }
+
+    /// decides if a connection can join the room, by looking at the room and the metadata of the connection. Returning `false` refuses it with `Error::Refused`.
+    pub type Admission<M = (), D = ()> = Arc<dyn Fn(&Room<M, D>, &Connection<M>) -> bool + Send + Sync>;
+
+    /// the admission check which a broadcaster shares with all of it's rooms.
+    struct Gate<M, D>(Option<Admission<M, D>>);
+
+    impl<M, D> Clone for Gate<M, D> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
+        }
+    }
+
+    impl<M, D> Default for Gate<M, D> {
+        fn default() -> Self {
+            Self(None)
+        }
+    }
+
+    impl<M, D> fmt::Debug for Gate<M, D> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("Gate").field(&self.0.is_some()).finish()
+        }
+    }
+
+    /// gives the key which a connection is counted with for the `per_key` quota, such as the ip address, user id or tenant in it's metadata. `None` only counts it in the total.
+    pub type QuotaKey<M = ()> = Arc<dyn Fn(&Connection<M>) -> Option<String> + Send + Sync>;
+
+    /// the quota key of a broadcaster.
+    struct Keyer<M>(Option<QuotaKey<M>>);
+
+    impl<M> Clone for Keyer<M> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
+        }
+    }
+
+    impl<M> Default for Keyer<M> {
+        fn default() -> Self {
+            Self(None)
+        }
+    }
+
+    impl<M> fmt::Debug for Keyer<M> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("Keyer").field(&self.0.is_some()).finish()
+        }
+    }
+
+    /// limits the data messages which `.serve()` reads from each connection with a token bucket. The bucket holds `burst` tokens and it's refilled `per_second` tokens in each second, every message takes a token and the ones which come when it's empty are handled by `action`.
+    #[derive(Debug, Clone)]
+    pub struct RateLimit {
+        pub per_second: f64,
+        pub burst: u32,
+        pub action: RateAction
+    }
+
+    /// what `.serve()` does with a message which comes over the rate limit.
+    #[derive(Debug, Clone)]
+    pub enum RateAction {
+        /// drop the message silently.
+        Drop,
+        /// drop the message and send given message to the client, so it knows it's too fast.
+        Warn(Utf8Bytes),
+        /// drop the message and close the connection with given close frame.
+        Close(Option<CloseFrame>)
+    }
+
+    /// a connection which waits on the waitlist of a room for a free seat. The result of it's admission is sent back when it's promoted.
+    #[derive(Debug)]
+    struct Waiter<M> {
+        connection: Connection<M>,
+        last_seen: Option<u64>,
+        promoted: oneshot::Sender<Result<Resume>>
+    }
+
+    /// what `.add_connection()` does when the room already has a connection with the same id.
+    #[derive(Debug, Clone, Default)]
+    pub enum DuplicatePolicy {
+        /// fail with `Error::DuplicateConnection` and drop the new receiver. It's the default.
+        #[default]
+        Reject,
+        /// close the existing connection with given close frame and put the new one in it's place.
+        Replace(Option<CloseFrame>),
+        /// keep both of them, like different devices of the same user. Messages are sent to each of them, closing or removing the id affects all of them.
+        KeepBoth
+    }
+
+    /// room implementation.
+    #[derive(Debug)]
+    pub struct Room<M = (), D = ()> {
+        pub id: String,
+        /// how many connections are in `connections`, it's kept up to date with it so the capacity is checked without counting them.
+        count: usize,
+        connections: HashMap<String, Vec<Connection<M>>>,
+        index: Index,
+        config: Shared<Config>,
+        presence: Shared<Announcer<M>>,
+        admission: Shared<Gate<M, D>>,
+        this: Weak<RwLock<Room<M, D>>>,
+        removed: bool,
+        persistent: bool,
+        state: RoomState<D>,
+        backpressure: Option<Backpressure>,
+        empty_since: Option<Instant>,
+        waitlist: VecDeque<Waiter<M>>,
+        history: Backlog<Message>,
+        order: Mutex<()>
+    }
+
+    /// type for each individual connection. The receiver is owned by a writer task, connection only holds the sending half of it's outbound queue.
+    /// Cloning a connection is cheap and the clones share the same socket, so one connection can be in many rooms at once.
+    #[derive(Debug)]
+    pub struct Connection<M = ()> {
+        pub id: String,
+        meta: Shared<Arc<M>>,
+        sender: Outbox<Message>,
+        writer: AbortHandle,
+        failure: watch::Receiver<Option<Error>>,
+        liveness: Arc<Liveness>,
+        permit: Arc<OnceLock<Permit>>
+    }
+
+    impl<M> Clone for Connection<M> {
+        fn clone(&self) -> Self {
+            Self {
+                id: self.id.clone(),
+                meta: self.meta.clone(),
+                sender: self.sender.clone(),
+                writer: self.writer.clone(),
+                failure: self.failure.clone(),
+                liveness: Arc::clone(&self.liveness),
+                permit: Arc::clone(&self.permit)
+            }
+        }
+    }
+
+    impl<M: Default> Connection<M> {
+        /// create a connection and spawn it's writer task with the default queue capacity:
+        pub fn create(id: String, receiver: SplitSink<WebSocket, Message>) -> Self {
+            Self::spawn(id, receiver, M::default(), DEFAULT_QUEUE_CAPACITY)
+        }
+
+        /// create a connection whose outbound queue can hold given amount of messages. Capacity must be greater than zero.
+        pub fn with_capacity(id: String, receiver: SplitSink<WebSocket, Message>, capacity: usize) -> Self {
+            Self::spawn(id, receiver, M::default(), capacity)
+        }
+    }
+
+    impl<M> Connection<M> {
+        /// create a connection which carries given metadata, such as the user, role or locale of the client. Predicates of the conditional methods can filter by it with `.meta()`.
+        pub fn with_meta(id: String, receiver: SplitSink<WebSocket, Message>, meta: M) -> Self {
+            Self::spawn(id, receiver, meta, DEFAULT_QUEUE_CAPACITY)
+        }
+
+        /// spawn the writer task of a new connection, which writes to given sink.
+        fn spawn<K>(id: String, receiver: K, meta: M, capacity: usize) -> Self where K: Sink<Message> + Unpin + Send + 'static, K::Error: std::error::Error + Send + Sync + 'static {
+            let (sender, writer, failure) = crate::spawn_writer(id.clone(), receiver, capacity, |message| matches!(message, Message::Close(_)));
+
+            Self {
+                id,
+                meta: Shared::new(Arc::new(meta)),
+                sender,
+                writer,
+                failure,
+                liveness: Arc::default(),
+                permit: Arc::default()
+            }
+        }
+
+        /// the metadata of the connection. Clones of the connection in other rooms share it.
+        pub fn meta(&self) -> Arc<M> {
+            self.meta.get()
+        }
+
+        /// change the metadata of the connection, every clone of it sees the new one. Use `Broadcaster::update_meta()` for announcing it to the rooms it's in.
+        pub fn set_meta(&self, meta: M) {
+            self.meta.set(Arc::new(meta));
+        }
+
+        /// when the last pong is received from the client.
+        pub fn last_pong(&self) -> Option<Instant> {
+            self.liveness.last_pong()
+        }
+
+        /// round trip time of the last heartbeat ping which is answered.
+        pub fn latency(&self) -> Option<Duration> {
+            self.liveness.latency()
+        }
+
+        /// how many heartbeat pings are not answered in a row.
+        pub fn missed_heartbeats(&self) -> u32 {
+            self.liveness.missed()
+        }
+
+        /// record a pong which is received from the client. `.serve()` does it by itself, call it from your own read loop if you use the heartbeat with `.handle()`.
+        pub fn record_pong(&self, payload: &[u8]) {
+            self.liveness.pong(payload);
+        }
+
+        /// close the connection gracefully. The messages which are queued before are written first, than the close frame is sent and the socket is closed. If all of that can't be done before the timeout, the writer is stopped, the socket is dropped and `Error::Timeout` is returned.
+        pub async fn close(self, close_frame: Option<CloseFrame>, timeout: Duration) -> Result<()> {
+            crate::shutdown_writer(&self.id, self.sender, &self.writer, self.failure, Message::Close(close_frame), timeout).await
+        }
+
+        /// check if both of them are the same connection, which means they share the same socket.
+        fn is(&self, other: &Self) -> bool {
+            self.failure.same_channel(&other.failure)
+        }
+
+        /// put the message into outbound queue of the connection. It fails if the queue is full or the writer task is stopped.
+        fn enqueue(&self, message: Message) -> Result<()> {
+            self.sender.offer(&self.id, message, Overflow::Reject).map(|_| ())
+        }
+
+        /// put the message into outbound queue of the connection by the backpressure policy without waiting. A message which has to wait for a place in the queue is given a ticket for `.settle()`.
+        fn queue(&self, message: Message, backpressure: &Backpressure, config: &Config) -> Result<Queued> {
+            let overflow = match backpressure {
+                Backpressure::Wait if config.send_timeout.is_zero() => Overflow::Reject,
+                Backpressure::Wait => return Ok(self.sender.reserve(&self.id, message)?.map_or(Queued::Taken(true), Queued::Waiting)),
+                Backpressure::DropOldest => Overflow::DropOldest,
+                Backpressure::DropNewest => Overflow::DropNewest,
+                Backpressure::Coalesce => Overflow::Coalesce(|message| matches!(message, Message::Text(_) | Message::Binary(_))),
+                Backpressure::Disconnect(close_frame) => Overflow::Close(Message::Close(close_frame.clone()))
+            };
+
+            let offered = self.sender.offer(&self.id, message, overflow);
+
+            if let (Backpressure::Disconnect(_), Err(Error::QueueFull(_))) = (backpressure, &offered) {
+                tokio::spawn(crate::abort_after(self.writer.clone(), self.failure.clone(), config.close_timeout));
+            }
+
+            offered.map(Queued::Taken)
+        }
+
+        /// wait until the message which is queued by `.queue()` takes it's place, at most for the `send_timeout` of the config. `false` is returned if the message is dropped because the queue is full.
+        async fn settle(&self, queued: Result<Queued>, config: &Config) -> Result<bool> {
+            match queued? {
+                Queued::Taken(taken) => Ok(taken),
+                Queued::Waiting(ticket) => self.sender.wait_turn(&self.id, ticket, config.send_timeout).await.map(|_| true)
+            }
+        }
+
+        /// how many messages wait in the outbound queue of the connection.
+        pub fn queued(&self) -> usize {
+            self.sender.queued()
+        }
+
+        /// how many messages are dropped from the outbound queue of the connection because it was full, by the backpressure policy of the rooms it's in. Clones of the connection share it.
+        pub fn dropped(&self) -> u64 {
+            self.sender.dropped()
+        }
+
+        // send the text message.
+        pub async fn send(&self, message: Utf8Bytes) -> Result<()> {
+            self.enqueue(Message::Text(message))
+        }
+
+        /// send the text message if the given condition in it's closure is true.
+        pub async fn send_if<F>(&self, message: Utf8Bytes, condition: F) -> Result<()> where F: Fn(&Connection<M>) -> bool {
+            match condition(self) {
+                true => self.enqueue(Message::Text(message)),
+                false => Ok(())
+            }
+        }
+
+        /// sen the text message if the given condition in it's closure is true.
+        pub async fn send_if_not<F>(&self, message: Utf8Bytes, condition: F) -> Result<()> where F: Fn(&Connection<M>) -> bool {
+            match condition(self) {
+                false => self.enqueue(Message::Text(message)),
+                true => Ok(())
+            }
+        }
+
+        /// send the ping.
+        pub async fn ping(&self, message: Bytes) -> Result<()> {
+            self.enqueue(Message::Ping(message))
+        }
+
+        /// send the ping if the given condition in it's closure is true.
+        pub async fn ping_if<F>(&self, message: Bytes, condition: F) -> Result<()> where F: Fn(&Connection<M>) -> bool {
+            match condition(self) {
+                true => self.enqueue(Message::Ping(message)),
+                false => Ok(())
+            }
+        }
+
+        /// sen the ping if the given condition in it's closure is true.
+        pub async fn ping_if_not<F>(&self, message: Bytes, condition: F) -> Result<()> where F: Fn(&Connection<M>) -> bool {
+            match condition(self) {
+                false => self.enqueue(Message::Ping(message)),
+                true => Ok(())
+            }
+        }
+
+        /// send the pong.
+        pub async fn pong(&self, message: Bytes) -> Result<()> {
+            self.enqueue(Message::Pong(message))
+        }
+
+        /// send the pong if the given condition in it's closure is true.
+        pub async fn pong_if<F>(&self, message: Bytes, condition: F) -> Result<()> where F: Fn(&Connection<M>) -> bool {
+            match condition(self) {
+                true => self.enqueue(Message::Pong(message)),
+                false => Ok(())
+            }
+        }
+
+        /// sen the pong if the given condition in it's closure is true.
+        pub async fn pong_if_not<F>(&self, message: Bytes, condition: F) -> Result<()> where F: Fn(&Connection<M>) -> bool {
+            match condition(self) {
+                false => self.enqueue(Message::Pong(message)),
+                true => Ok(())
+            }
+        }
+    }
+
+    impl<M: Send + Sync + 'static, D: Send + Sync + 'static> Room<M, D> {
+        /// add a connection to the room with given id. If the room already has a connection with that id, the `duplicate_policy` of the config decides what happens: by default the receiver is dropped and `Error::DuplicateConnection` is returned:
+        pub fn add_connection(&mut self, id: &str, receiver: SplitSink<WebSocket, Message>) -> Result<()> where M: Default {
+            self.join(Connection::create(id.to_string(), receiver))
+        }
+
+        /// add a connection to the room like `.add_connection()`, with given metadata.
+        pub fn add_connection_with_meta(&mut self, id: &str, receiver: SplitSink<WebSocket, Message>, meta: M) -> Result<()> {
+            self.join(Connection::with_meta(id.to_string(), receiver, meta))
+        }
+
+        /// put an existing connection into the room too, such as a clone of a connection from another room. It shares the socket with the other rooms it's in. Joining a room which it's already in does nothing, another connection with the same id is handled by the `duplicate_policy` of the config.
+        pub fn join(&mut self, connection: Connection<M>) -> Result<()> {
+            self.admit(connection, None).map(|_| ())
+        }
+
+        /// put the connection into the room like `.join()`, but instead of the usual replay, send it the messages which are broadcasted after the last sequence number it has seen, such as when a client reconnects. If some of them are not kept anymore, nothing is replayed and `Resume::GapTooLarge` is returned, so the client can load everything again.
+        pub fn resume(&mut self, connection: Connection<M>, last_seen: u64) -> Result<Resume> {
+            self.admit(connection, Some(last_seen))
+        }
+
+        /// put the connection into the room if it has a free seat and no one waits for it, than seat it.
+        fn admit(&mut self, connection: Connection<M>, last_seen: Option<u64>) -> Result<Resume> {
+            let existing = self.connections.get(&connection.id);
+
+            if existing.is_some_and(|existing| existing.iter().any(|existing| existing.is(&connection))) {
+                return Ok(Resume::Replayed(0));
+            }
+
+            // a connection which replaces another one takes it's seat.
+            let replacing = existing.is_some() && matches!(self.config.get().duplicate_policy, DuplicatePolicy::Replace(_));
+
+            self.promote();
+
+            if !replacing && (self.is_full() || self.waiting() > 0) {
+                return Err(Error::RoomFull(self.id.clone()));
+            }
+
+            self.seat(connection, last_seen)
+        }
+
+        /// put the connection into the room by the admission check and the duplicate policy, and replay the history to it.
+        fn seat(&mut self, connection: Connection<M>, last_seen: Option<u64>) -> Result<Resume> {
+            let config = self.config.get();
+            let existing = self.connections.contains_key(&connection.id);
+
+            if let (true, DuplicatePolicy::Reject) = (existing, &config.duplicate_policy) {
+                return Err(Error::DuplicateConnection(connection.id.clone()));
+            }
+
+            if self.admission.get().0.is_some_and(|check| !check(self, &connection)) {
+                return Err(Error::Refused(connection.id.clone()));
+            }
+
+            if let (true, DuplicatePolicy::Replace(close_frame)) = (existing, config.duplicate_policy) {
+                // the new connection takes the place of the old ones right away, so the room is not told to be empty.
+                let replaced = self.detach(&connection.id);
+
+                self.departed(&replaced);
+
+                for existing in replaced {
+                    tokio::spawn(existing.close(close_frame.clone(), config.close_timeout));
+                }
+            }
+
+            Ok(self.insert(connection, last_seen))
+        }
+
+        /// put the connection on the waitlist of the room, it's admission is sent to the returned receiver when a seat frees up. The waiters which stopped waiting are dropped first.
+        fn wait(&mut self, connection: Connection<M>, last_seen: Option<u64>) -> oneshot::Receiver<Result<Resume>> {
+            let (promoted, receiver) = oneshot::channel();
+
+            self.waitlist.retain(|waiter| !waiter.promoted.is_closed());
+            self.waitlist.push_back(Waiter { connection, last_seen, promoted });
+
+            receiver
+        }
+
+        /// give the free seats to the connections on the waitlist, in the order they came. The ones which stopped waiting are skipped.
+        fn promote(&mut self) {
+            while !self.is_full() {
+                let Some(waiter) = self.waitlist.pop_front() else {
+                    return;
+                };
+
+                if waiter.promoted.is_closed() {
+                    continue;
+                }
+
+                let id = waiter.connection.id.clone();
+                let failure = waiter.connection.failure.clone();
+                let admission = self.seat(waiter.connection, waiter.last_seen);
+
+                // it can stop waiting in the meantime, than it's seat is freed again.
+                if let Err(Ok(_)) = waiter.promoted.send(admission) {
+                    self.evict(&id, &failure);
+                }
+            }
+        }
+
+        /// put the connection into the room and the index and start watching it's writer. The history, or the messages after the last seen sequence number, is replayed to it first, as much as it's queue takes.
+        fn insert(&mut self, connection: Connection<M>, last_seen: Option<u64>) -> Resume {
+            let history = self.config.get().history;
+            let max_age = history.as_ref().and_then(|history| history.max_age);
+
+            let replay = match last_seen {
+                Some(last_seen) => self.history.since(last_seen, max_age).map_err(|oldest| Resume::GapTooLarge { last_seen, oldest }),
+                None => Ok(self.history.recent(history.map_or(0, |history| history.replay), max_age))
+            };
+
+            let resume = match replay {
+                // a resumed connection gets all the messages it missed or none of them, so it never skips some of them silently.
+                Ok(messages) if last_seen.is_some() && messages.len() > connection.sender.free() => Resume::TooManyMissed { missed: messages.len(), free: connection.sender.free() },
+                Ok(messages) => Resume::Replayed(messages.into_iter().map_while(|message| connection.enqueue(message).ok()).count()),
+                Err(gap) => gap
+            };
+
+            self.announce(PresenceKind::Joined, &connection);
+            self.empty_since = None;
+
+            crate::emit(&self.config.get().on_event, || Event::Joined { room: self.id.clone(), connection: connection.id.clone() });
+
+            self.watch(&connection);
+            self.index.insert(&connection.id, &self.id);
+            self.connections.entry(connection.id.clone()).or_default().push(connection);
+            self.count += 1;
+
+            resume
+        }
+
+        /// wait in the background until the writer of the connection stops. If it's failed, the connection is evicted when the config says so. If it's closed, it's
+        /// removed silently, since it can be closed through another room it's in.
+        fn watch(&self, connection: &Connection<M>) {
+            let room = self.this.clone();
+            let room_id = self.id.clone();
+            let config = self.config.clone();
+            let id = connection.id.clone();
+            let failure = connection.failure.clone();
+
+            tokio::spawn(async move {
+                let Some(error) = crate::writer_failure(failure.clone()).await else {
+                    if let Some(room) = room.upgrade() {
+                        room.write().await.evict(&id, &failure);
+                    }
+
+                    return;
+                };
+
+                let config = config.get();
+
+                crate::emit(&config.on_event, || Event::SendFailed { room: room_id, connection: id.clone(), error: error.clone() });
+
+                if !config.evict_on_error {
+                    return;
+                }
+
+                evict(room, vec![(id, failure, error)], config.on_evict).await;
+            });
+        }
+
+        /// remove the connection if it's writer is the one which published given failure. A connection which joined later with the same id is kept.
+        fn evict(&mut self, id: &String, failure: &watch::Receiver<Option<Error>>) -> Option<Connection<M>> {
+            let connections = self.connections.get_mut(id)?;
+            let pos = connections.iter().position(|connection| connection.failure.same_channel(failure))?;
+            let connection = connections.remove(pos);
+
+            self.count -= 1;
+
+            if connections.is_empty() {
+                self.connections.remove(id);
+                self.index.remove(id, &self.id);
+            }
+
+            self.left(std::slice::from_ref(&connection));
+            self.promote();
+
+            Some(connection)
+        }
+
+        /// remove the connections with given id from room. If they're in other rooms too, they stay there.
+        pub fn remove_connection(&mut self, id: String) {
+            self.take(&id);
+        }
+
+        /// check if a connection exist and return if it's in an option.
+        pub fn check_connection(&mut self, id: &String) -> Option<&Connection<M>> {
+            self.connections.get(id)?.first()
+        }
+
+        /// iterate through the connections of the room, in no particular order.
+        pub fn connections(&self) -> impl Iterator<Item = &Connection<M>> {
+            self.connections.values().flatten()
+        }
+
+        /// how many connections the room has. Connections which share an id are counted one by one.
+        pub fn len(&self) -> usize {
+            self.count
+        }
+
+        /// check if the room has no connections.
+        pub fn is_empty(&self) -> bool {
+            self.connections.is_empty()
+        }
+
+        /// check if the room is at it's capacity.
+        pub fn is_full(&self) -> bool {
+            self.state.capacity.is_some_and(|capacity| self.len() >= capacity)
+        }
+
+        /// how many connections wait on the waitlist of the room. Seats which are added by raising the capacity are given to them on the next join or leave.
+        pub fn waiting(&self) -> usize {
+            self.waitlist.iter().filter(|waiter| !waiter.promoted.is_closed()).count()
+        }
+
+        /// since when the room is empty, `None` if it has connections.
+        pub fn empty_since(&self) -> Option<Instant> {
+            self.empty_since
+        }
+
+        /// the members of the room with their metadata. Connections which share an id are listed once, with the metadata of the first one.
+        pub fn members(&self) -> impl Iterator<Item = (&String, Arc<M>)> {
+            self.connections.iter().filter_map(|(id, connections)| Some((id, connections.first()?.meta())))
+        }
+
+        /// announce the connections which are taken out of the room and tell the event callback about them, and about the room if it's empty now.
+        fn left(&mut self, connections: &[Connection<M>]) {
+            self.departed(connections);
+
+            if !connections.is_empty() && self.connections.is_empty() {
+                self.empty_since = Some(Instant::now());
+
+                crate::emit(&self.config.get().on_event, || Event::RoomEmptied { room: self.id.clone() });
+            }
+        }
+
+        /// announce the connections which are taken out of the room and tell the event callback that they left.
+        fn departed(&self, connections: &[Connection<M>]) {
+            let hook = self.config.get().on_event;
+
+            for connection in connections {
+                self.announce(PresenceKind::Left, connection);
+
+                crate::emit(&hook, || Event::Left { room: self.id.clone(), connection: connection.id.clone() });
+            }
+        }
+
+        /// announce the event of the connection to the other members of the room, if the broadcaster has a presence format. Announcements skip the backpressure policy, they're dropped if a queue is full.
+        fn announce(&self, kind: PresenceKind, connection: &Connection<M>) {
+            let Some(format) = self.presence.get().0 else {
+                return;
+            };
+
+            let meta = connection.meta();
+
+            let event = PresenceEvent {
+                kind,
+                room: &self.id,
+                id: &connection.id,
+                meta: meta.as_ref()
+            };
+
+            let Some(message) = format(&event) else {
+                return;
+            };
+
+            for member in self.connections().filter(|member| !member.is(connection)) {
+                let _ = member.enqueue(Message::Text(message.clone()));
+            }
+        }
+
+        /// the state of the room, such as it's title, owner and topic.
+        pub fn state(&self) -> &RoomState<D> {
+            &self.state
+        }
+
+        /// the state of the room, for changing it. It's kept as long as the room lives.
+        pub fn state_mut(&mut self) -> &mut RoomState<D> {
+            &mut self.state
+        }
+
+        /// the backpressure policy which broadcasting methods of the room use.
+        pub fn backpressure(&self) -> Backpressure {
+            self.backpressure.clone().unwrap_or_else(|| self.config.get().backpressure)
+        }
+
+        /// use given backpressure policy in that room instead of the one in the config, `None` goes back to the config.
+        pub fn set_backpressure(&mut self, backpressure: Option<Backpressure>) {
+            self.backpressure = backpressure;
+        }
+
+        /// the messages which are kept in the history of the room, oldest first.
+        pub fn history(&self) -> Vec<Message> {
+            let max_age = self.config.get().history.and_then(|history| history.max_age);
+
+            self.history.recent(usize::MAX, max_age)
+        }
+
+        /// send the last `count` messages of the history to the connections with given id, such as when a client asks for the messages before it joined.
+        pub fn replay(&self, id: &String, count: usize) -> Result<()> {
+            let connections = self.connections.get(id).ok_or_else(|| Error::UnknownConnection(id.clone()))?;
+            let max_age = self.config.get().history.and_then(|history| history.max_age);
+
+            for connection in connections {
+                for message in self.history.recent(count, max_age) {
+                    connection.enqueue(message)?;
+                }
+            }
+
+            Ok(())
+        }
+
+        /// forget the history of the room.
+        pub fn clear_history(&self) {
+            self.history.clear();
+        }
+
+        /// sequence number of the last broadcast to the whole room, zero if there's none yet.
+        pub fn sequence(&self) -> u64 {
+            self.history.sequence()
+        }
+
+        /// broadcast the message which is built with it's sequence number to every connection and keep it in the history if it's on. Such broadcasts of a room are queued one by one, so every connection gets them in the order of their sequence numbers.
+        /// They're only ordered while the message is stamped and queued, a message which waits for a place in the queue of a slow connection waits behind the earlier ones after that, so it doesn't hold up the other broadcasts.
+        async fn deliver_sequenced<F>(&self, message: F) -> DeliveryReport where F: FnOnce(u64) -> Message {
+            let config = self.config.get();
+            let backpressure = self.backpressure();
+
+            let (sequence, queued) = {
+                let _order = self.order.lock().unwrap_or_else(PoisonError::into_inner);
+                let (sequence, message) = self.history.record(message, config.history.as_ref());
+
+                (sequence, queue_all(self.connections().map(|connection| (&self.this, self.id.as_str(), connection)), message, &backpressure, &config))
+            };
+
+            let mut report = fan_out(queued, &config).await;
+
+            report.sequence = Some(sequence);
+
+            report
+        }
+
+        /// take the connections with given id out of the room and the index, and give their seats to the waitlist.
+        fn take(&mut self, id: &String) -> Vec<Connection<M>> {
+            let connections = self.detach(id);
+
+            self.left(&connections);
+            self.promote();
+
+            connections
+        }
+
+        /// take the connections with given id out of the room and the index, without announcing them or promoting the waitlist.
+        fn detach(&mut self, id: &String) -> Vec<Connection<M>> {
+            let connections = self.connections.remove(id).unwrap_or_default();
+
+            self.count -= connections.len();
+
+            if !connections.is_empty() {
+                self.index.remove(id, &self.id);
+            }
+
+            connections
+        }
+
+        /// take every connection which satisfies the condition out of the room and the index.
+        fn take_where<F>(&mut self, condition: F) -> Vec<Connection<M>> where F: Fn(&Connection<M>) -> bool {
+            let mut taken = vec![];
+
+            for connections in self.connections.values_mut() {
+                let (matched, remaining): (Vec<_>, Vec<_>) = std::mem::take(connections).into_iter().partition(|connection| condition(connection));
+
+                *connections = remaining;
+                taken.extend(matched);
+            }
+
+            self.count -= taken.len();
+
+            self.connections.retain(|id, connections| match connections.is_empty() {
+                true => {
+                    self.index.remove(id, &self.id);
+
+                    false
+                },
+                false => true
+            });
+
+            self.left(&taken);
+            self.promote();
+
+            taken
+        }
+
+        /// push the message into the queue of every connection which satisfies the condition, concurrently, and report what happened for each of them. A full queue is handled by the backpressure policy of the room.
+        async fn deliver<F>(&self, message: Message, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            let config = self.config.get();
+            let backpressure = self.backpressure.clone().unwrap_or_else(|| config.backpressure.clone());
+            let (targets, skipped): (Vec<_>, Vec<_>) = self.connections.values().flatten().partition(|connection| condition(connection));
+            let mut report = fan_out(queue_all(targets.into_iter().map(|connection| (&self.this, self.id.as_str(), connection)), message, &backpressure, &config), &config).await;
+
+            report.skipped = skipped.into_iter().map(|connection| connection.id.clone()).collect();
+
+            report
+        }
+
+        /// Broadcast the message directly.
+        pub async fn broadcast(&self, message: &Utf8Bytes) -> DeliveryReport {
+            self.deliver_sequenced(|_| Message::Text(message.clone())).await
+        }
+
+        /// broadcast the message which is built with it's sequence number, so the client can tell it when it resumes.
+        pub async fn broadcast_sequenced<F>(&self, message: F) -> DeliveryReport where F: FnOnce(u64) -> Utf8Bytes {
+            self.deliver_sequenced(|sequence| Message::Text(message(sequence))).await
+        }
+
+        /// broadcast the message if the given condition in it's closure is true.
+        pub async fn broadcast_if<F>(&self, message: &Utf8Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Text(message.clone()), condition).await
+        }
+
+        /// broadcast the message if the given condition in it's closure is false.
+        pub async fn broadcast_if_not<F>(&self, message: &Utf8Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Text(message.clone()), |connection| !condition(connection)).await
+        }
+
+        /// Broadcast the ping message directly.
+        pub async fn ping(&self, bytes: &Bytes) -> DeliveryReport {
+            self.deliver(Message::Ping(bytes.clone()), |_| true).await
+        }
+
+        /// broadcast the ping message if the given condition in it's closure is true.
+        pub async fn ping_if<F>(&self, bytes: &Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Ping(bytes.clone()), condition).await
+        }
+
+        /// broadcast the ping message if the given condition in it's closure is false.
+        pub async fn ping_if_not<F>(&self, bytes: &Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Ping(bytes.clone()), |connection| !condition(connection)).await
+        }
+
+        /// Broadcast the pong message directly.
+        pub async fn pong(&self, bytes: &Bytes) -> DeliveryReport {
+            self.deliver(Message::Pong(bytes.clone()), |_| true).await
+        }
+
+        /// broadcast the pong message if the given condition in it's closure is true.
+        pub async fn pong_if<F>(&self, bytes: &Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Pong(bytes.clone()), condition).await
+        }
+
+        /// broadcast the pong message if the given condition in it's closure is false.
+        pub async fn pong_if_not<F>(&self, bytes: &Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Pong(bytes.clone()), |connection| !condition(connection)).await
+        }
+
+        /// Broadcast the raw binary bytes directly.
+        pub async fn binary(&self, bytes: &Bytes) -> DeliveryReport {
+            self.deliver_sequenced(|_| Message::Binary(bytes.clone())).await
+        }
+
+        /// broadcast the raw binary bytes which are built with their sequence number, so the client can tell it when it resumes.
+        pub async fn binary_sequenced<F>(&self, bytes: F) -> DeliveryReport where F: FnOnce(u64) -> Bytes {
+            self.deliver_sequenced(|sequence| Message::Binary(bytes(sequence))).await
+        }
+
+        /// broadcast the raw binary bytes if the given condition in it's closure is true.
+        pub async fn binary_if<F>(&self, bytes: &Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Binary(bytes.clone()), condition).await
+        }
+
+        /// broadcast the raw binary bytes if the given condition in it's closure is false.
+        pub async fn binary_if_not<F>(&self, bytes: &Bytes, condition: F) -> DeliveryReport where F: Fn(&Connection<M>) -> bool {
+            self.deliver(Message::Binary(bytes.clone()), |connection| !condition(connection)).await
+        }
+
+        /// Close all connections and remove it from it's room but not close it.
+        pub async fn close(&mut self, close_frame: Option<CloseFrame>) -> CloseReport {
+            let connections = self.take_where(|_| true);
+
+            close_all(connections, close_frame, self.config.get().close_timeout).await
+        }
+
+        /// it's most convenient way to close a single connection but keeping room open. If more than one connection has that id, all of them are closed and the first error is returned. Closing a connection closes it's socket, so it leaves the other rooms it's in too.
+        pub async fn close_conn(&mut self, close_frame: Option<CloseFrame>, id: &String) -> Result<()> {
+            match self.take(id) {
+                connections if connections.is_empty() => Err(Error::UnknownConnection(id.clone())),
+                connections => match close_all(connections, close_frame, self.config.get().close_timeout).await.unclean.into_iter().next() {
+                    Some((_, error)) => Err(error),
+                    None => Ok(())
+                }
+            }
+        }
+
+        /// close each connection and remove them from room if the given condition in it's closure is true.
+        pub async fn close_if<F>(&mut self, close_frame: Option<CloseFrame>, condition: F) -> CloseReport where F: Fn(&Connection<M>) -> bool, {
+            let connections = self.take_where(condition);
+
+            close_all(connections, close_frame, self.config.get().close_timeout).await
+        }
+
+        /// close each connection and remove them from room if the given condition in it's closure is false.
+        pub async fn close_if_not<F>(&mut self, close_frame: Option<CloseFrame>, condition: F) -> CloseReport where F: Fn(&Connection<M>) -> bool, {
+            let connections = self.take_where(|connection| !condition(connection));
+
+            close_all(connections, close_frame, self.config.get().close_timeout).await
+        }
+    }
+
+    /// put the message into the queue of each connection by given backpressure policy, without waiting for any of them.
+    fn queue_all<'a, M, D, I>(targets: I, message: Message, backpressure: &Backpressure, config: &Config) -> Vec<Queueing<'a, M, D>> where I: IntoIterator<Item = (&'a WeakRoom<M, D>, &'a str, &'a Connection<M>)>, M: Send + Sync + 'static, D: Send + Sync + 'static {
+        targets.into_iter().map(|(room, room_id, connection)| (room, room_id, connection, connection.queue(message.clone(), backpressure, config))).collect()
+    }
+
+    /// wait until the messages which are queued by `queue_all()` take their places, concurrently, and report what happened for each connection. Failed sends are told to the event callback with the room of the connection
+    /// and the ones which timed out are evicted from that room if `evict_on_timeout` is on. Both the broadcasting methods of `Room` and `.send_to()` of `Broadcaster` deliver through it.
+    async fn fan_out<M, D>(queued: Vec<Queueing<'_, M, D>>, config: &Config) -> DeliveryReport where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        let mut report = DeliveryReport::default();
+        let mut sending: FuturesUnordered<_> = queued.into_iter().map(|(room, room_id, connection, queued)| async move {
+            (room, room_id, connection, connection.settle(queued, config).await)
+        }).collect();
+
+        let mut timed_out = HashMap::new();
+
+        while let Some((room, room_id, connection, result)) = sending.next().await {
+            match result {
+                Ok(true) => report.delivered.push(connection.id.clone()),
+                Ok(false) => report.dropped.push(connection.id.clone()),
+                Err(error) => {
+                    crate::emit(&config.on_event, || Event::SendFailed { room: room_id.to_string(), connection: connection.id.clone(), error: error.clone() });
+
+                    if config.evict_on_timeout && matches!(error, Error::Timeout(_)) {
+                        // they're grouped by their rooms, so each room is locked once for evicting them.
+                        timed_out.entry(room_id).or_insert_with(|| (room, vec![])).1.push((connection.id.clone(), connection.failure.clone(), error.clone()));
+                    }
+
+                    report.failed.push((connection.id.clone(), error));
+                }
+            }
+        }
+
+        for (room, evictions) in timed_out.into_values() {
+            tokio::spawn(evict(room.clone(), evictions, config.on_evict.clone()));
+        }
+
+        report
+    }
+
+    /// remove the given connections from the room and call `on_evict` for each of them which was still there. Their writers are stopped, so a stalled socket is dropped immediately.
+    async fn evict<M: Send + Sync + 'static, D: Send + Sync + 'static>(room: Weak<RwLock<Room<M, D>>>, evictions: Evictions, on_evict: Option<EvictHook>) {
+        let Some(room) = room.upgrade() else {
+            return;
+        };
+
+        let (room_id, evicted) = {
+            let mut room = room.write().await;
+
+            let evicted: Vec<(String, Error)> = evictions.into_iter().filter_map(|(id, failure, error)| {
+                room.evict(&id, &failure)?.writer.abort();
+
+                Some((id, error))
+            }).collect();
+
+            (room.id.clone(), evicted)
+        };
+
+        if let Some(on_evict) = on_evict {
+            for (connection, error) in evicted {
+                on_evict(&Eviction { room: room_id.clone(), connection, error });
+            }
+        }
+    }
+
+    /// ping every connection of the broadcaster on each tick and close the ones which missed too many pings, until the heartbeat is turned off or the broadcaster is dropped.
+    async fn heartbeat<M, D>(broadcaster: Weak<RwLock<Broadcaster<M, D>>>, config: Shared<Config>) where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        loop {
+            let Some(settings) = config.get().heartbeat else {
+                return;
+            };
+
+            tokio::time::sleep(settings.interval).await;
+
+            let Some(broadcaster) = broadcaster.upgrade() else {
+                return;
+            };
+
+            let rooms: Vec<RoomHandle<M, D>> = broadcaster.read().await.rooms().cloned().collect();
+
+            drop(broadcaster);
+
+            let mut pinged = HashSet::new();
+            let mut stale = vec![];
+
+            for room in rooms {
+                for connection in room.read().await.connections() {
+                    if !pinged.insert(Arc::as_ptr(&connection.liveness) as usize) {
+                        continue;
+                    }
+
+                    let (missed, payload) = connection.liveness.ping();
+
+                    match missed >= settings.max_missed.max(1) {
+                        true => stale.push(connection.clone()),
+                        false => {
+                            let _ = connection.enqueue(Message::Ping(Bytes::copy_from_slice(&payload)));
+                        }
+                    }
+                }
+            }
+
+            let close_timeout = config.get().close_timeout;
+
+            for connection in stale {
+                tokio::spawn(connection.close(settings.close_frame.clone(), close_timeout));
+            }
+        }
+    }
+
+    /// remove the rooms which are empty for too long and close the expired ones on each tick, until the reaper is turned off or the broadcaster is dropped.
+    async fn reaper<M, D>(broadcaster: Weak<RwLock<Broadcaster<M, D>>>, config: Shared<Config>) where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        loop {
+            let Some(settings) = config.get().reaper else {
+                return;
+            };
+
+            tokio::time::sleep(settings.interval).await;
+
+            let Some(broadcaster) = broadcaster.upgrade() else {
+                return;
+            };
+
+            let rooms: Vec<RoomHandle<M, D>> = broadcaster.read().await.rooms().cloned().collect();
+            let now = SystemTime::now();
+            let mut stale = vec![];
+            let mut expired = vec![];
+
+            for room in rooms {
+                let room = room.read().await;
+
+                if room.state.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                    expired.push(room.id.clone());
+                } else if !room.persistent && room.state.expires_at.is_none() && room.empty_since.zip(settings.empty_ttl).is_some_and(|(since, ttl)| since.elapsed() >= ttl) {
+                    stale.push(room.id.clone());
+                }
+            }
+
+            let expired: Vec<RoomHandle<M, D>> = {
+                let mut this = broadcaster.write().await;
+
+                // a connection can join a stale room in the meantime, it's checked again while the room is locked.
+                for id in &stale {
+                    this.remove_if_empty(id).await;
+                }
+
+                expired.iter().filter_map(|id| this.rooms.remove(id)).collect()
+            };
+
+            drop(broadcaster);
+
+            for room in expired {
+                let (id, connections) = {
+                    let mut room = room.write().await;
+
+                    room.removed = true;
+                    room.waitlist.clear();
+
+                    (room.id.clone(), room.take_where(|_| true))
+                };
+
+                crate::emit(&config.get().on_event, || Event::RoomRemoved { room: id });
+
+                tokio::spawn(close_all(connections, settings.close_frame.clone(), config.get().close_timeout));
+            }
+        }
+    }
+
+    /// close the given connections concurrently and collect which of them are closed cleanly.
+    async fn close_all<M>(connections: Vec<Connection<M>>, close_frame: Option<CloseFrame>, timeout: Duration) -> CloseReport {
+        let closing = connections.into_iter().map(|connection| {
+            let close_frame = close_frame.clone();
+
+            async move {
+                let id = connection.id.clone();
+
+                (id, connection.close(close_frame, timeout).await)
+            }
+        });
+
+        let mut report = CloseReport::default();
+
+        for (id, result) in join_all(closing).await {
+            match result {
+                Ok(()) => report.clean.push(id),
+                Err(error) => report.unclean.push((id, error))
+            }
+        }
+
+        report
+    }
+
+    impl Broadcaster {
+        /// get receiver and stream, similar to ".handle()" method of actix-ws.
+        pub fn configure(socket: WebSocket) -> (SplitSink<WebSocket, Message>, SplitStream<WebSocket>) {
+            socket.split()
+        }
+    }
+
+    impl<M: Send + Sync + 'static, D: Send + Sync + 'static> Broadcaster<M, D> {
+        /// start the heartbeat in the background if it's set in the config and it's not running already. `.handle()` and `.serve()` start it by themselves, call it if you add connections in another way. Turning it off in the config stops it at it's next tick.
+        pub async fn start_heartbeat(broadcaster: &Arc<RwLock<Self>>) {
+            broadcaster.write().await.spawn_heartbeat(Arc::downgrade(broadcaster));
+        }
+
+        fn spawn_heartbeat(&mut self, this: Weak<RwLock<Self>>) {
+            let running = self.heartbeat.as_ref().is_some_and(|heartbeat| !heartbeat.is_finished());
+
+            if running || self.config.get().heartbeat.is_none() {
+                return;
+            }
+
+            self.heartbeat = Some(tokio::spawn(heartbeat(this, self.config.clone())).abort_handle());
+        }
+
+        /// start the reaper in the background if it's set in the config and it's not running already. `.handle()` and `.serve()` start it by themselves, call it if you create rooms in another way, such as with `.create_room()`. Turning it off in the config stops it at it's next tick.
+        pub async fn start_reaper(broadcaster: &Arc<RwLock<Self>>) {
+            broadcaster.write().await.spawn_reaper(Arc::downgrade(broadcaster));
+        }
+
+        fn spawn_reaper(&mut self, this: Weak<RwLock<Self>>) {
+            let running = self.reaper.as_ref().is_some_and(|reaper| !reaper.is_finished());
+
+            if running || self.config.get().reaper.is_none() {
+                return;
+            }
+
+            self.reaper = Some(tokio::spawn(reaper(this, self.config.clone())).abort_handle());
+        }
+
+        /// create new broadcaster.
+        pub fn new() -> Arc<RwLock<Self>> {
+            Arc::new(RwLock::new(Self::default()))
+        }
+
+        /// create new broadcaster with given settings.
+        pub fn with_config(config: Config) -> Arc<RwLock<Self>> {
+            Arc::new(RwLock::new(Self {
+                rooms: HashMap::new(),
+                index: Index::default(),
+                config: Shared::new(config),
+                presence: Shared::default(),
+                admission: Shared::default(),
+                quota_key: Shared::default(),
+                rate_limit: Shared::default(),
+                quotas: Quotas::default(),
+                heartbeat: None,
+                reaper: None
+            }))
+        }
+
+        /// get a copy of the current settings of broadcaster.
+        pub fn config(&self) -> Config {
+            self.config.get()
+        }
+
+        /// replace the settings of broadcaster. The new settings are applied to the existing rooms too.
+        pub fn set_config(&self, config: Config) {
+            self.config.set(config);
+        }
+
+        /// limit the data messages which `.serve()` reads from each connection, the connections which are already served are limited too. `None` removes the limit.
+        pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+            self.rate_limit.set(limit);
+        }
+
+        /// announce the connections which join, leave or update their metadata to the other members of each room they're in, with the message which is built by given format. `None` stops announcing.
+        pub fn set_presence(&self, format: Option<PresenceFormat<M>>) {
+            self.presence.set(Announcer(format));
+        }
+
+        /// handle the all thing. If you use that api, there is no need to any other configuration for grouping and identifying connections. Keep the returned `Membership` while the socket is handled, the connection is removed from it's rooms when it's dropped:
+        pub async fn handle(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: SplitSink<WebSocket, Message>) -> Result<Membership<M, D>> where M: Default, D: Default {
+            Self::handle_with_meta(broadcaster, room_id, conn_id, receiver, M::default()).await
+        }
+
+        /// handle the connection like `.handle()`, with given metadata. Predicates of the conditional methods can filter the connection by it:
+        pub async fn handle_with_meta(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: SplitSink<WebSocket, Message>, meta: M) -> Result<Membership<M, D>> where D: Default {
+            Self::enter(broadcaster, room_id, Connection::with_meta(conn_id.to_string(), receiver, meta), None).await.map(|(membership, _)| membership)
+        }
+
+        /// handle the connection like `.handle()`, but instead of the usual replay, send it the messages which are broadcasted in the room after the last sequence number it has seen, such as when a client reconnects. `Resume` tells if they're replayed or the gap is too large for that.
+        pub async fn resume(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: SplitSink<WebSocket, Message>, last_seen: u64) -> Result<(Membership<M, D>, Resume)> where M: Default, D: Default {
+            Self::resume_with_meta(broadcaster, room_id, conn_id, receiver, M::default(), last_seen).await
+        }
+
+        /// resume the connection like `.resume()`, with given metadata.
+        pub async fn resume_with_meta(broadcaster: &Arc<RwLock<Self>>, room_id: &str, conn_id: &str, receiver: SplitSink<WebSocket, Message>, meta: M, last_seen: u64) -> Result<(Membership<M, D>, Resume)> where D: Default {
+            Self::enter(broadcaster, room_id, Connection::with_meta(conn_id.to_string(), receiver, meta), Some(last_seen)).await
+        }
+
+        /// drive the socket by itself: the connection joins the room with given id, every data message is given to the handler and when the client closes the socket or reading it fails, the connection is removed from it's rooms. Pings are answered by the socket itself as long as it's read, so pings and pongs don't reach the handler:
+        pub async fn serve<F, Fut>(broadcaster: &Arc<RwLock<Self>>, socket: WebSocket, room_id: &str, conn_id: &str, handler: F) -> Result<()> where F: FnMut(Session<M, D>, Incoming) -> Fut, Fut: Future<Output = ()>, M: Default, D: Default {
+            Self::serve_with_meta(broadcaster, socket, room_id, conn_id, M::default(), handler).await
+        }
+
+        /// serve the socket like `.serve()`, with given metadata.
+        pub async fn serve_with_meta<F, Fut>(broadcaster: &Arc<RwLock<Self>>, socket: WebSocket, room_id: &str, conn_id: &str, meta: M, mut handler: F) -> Result<()> where F: FnMut(Session<M, D>, Incoming) -> Fut, Fut: Future<Output = ()>, D: Default {
+            let (receiver, mut stream) = socket.split();
+            let connection = Connection::with_meta(conn_id.to_string(), receiver, meta);
+            let (membership, _) = Self::enter(broadcaster, room_id, connection.clone(), None).await?;
+
+            let session = Session {
+                broadcaster: Arc::clone(broadcaster),
+                room_id: room_id.to_string(),
+                connection
+            };
+
+            let stopped = crate::writer_failure(session.connection.failure.clone());
+            let rate_limit = broadcaster.read().await.rate_limit.clone();
+            let mut bucket = Bucket::new();
+
+            tokio::pin!(stopped);
+
+            loop {
+                // stop as soon as the connection is closed from our side too, such as by the heartbeat, even if the client never answers it.
+                let message = tokio::select! {
+                    message = stream.next() => message,
+                    _ = &mut stopped => break
+                };
+
+                let incoming = match message {
+                    Some(Ok(Message::Text(text))) => Incoming::Text(text),
+                    Some(Ok(Message::Binary(bytes))) => Incoming::Binary(bytes),
+                    Some(Ok(Message::Pong(payload))) => {
+                        session.connection.record_pong(&payload);
+
+                        continue;
+                    },
+                    // pings are answered by the socket itself while it's read.
+                    Some(Ok(Message::Ping(_))) => continue,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break
+                };
+
+                let limit = rate_limit.get().filter(|limit| !bucket.take(limit.per_second, limit.burst));
+
+                match limit.map(|limit| limit.action) {
+                    None => handler(session.clone(), incoming).await,
+                    Some(RateAction::Drop) => (),
+                    Some(RateAction::Warn(warning)) => {
+                        let _ = session.connection.enqueue(Message::Text(warning));
+                    },
+                    Some(RateAction::Close(close_frame)) => {
+                        let close_timeout = broadcaster.read().await.config.get().close_timeout;
+                        let _ = session.connection.clone().close(close_frame, close_timeout).await;
+
+                        break;
+                    }
+                }
+            }
+
+            membership.release().await;
+
+            Ok(())
+        }
+
+        /// put the connection into the room with given id, creating the room if it doesn't exist, and give the membership of it.
+        async fn enter(broadcaster: &Arc<RwLock<Self>>, room_id: &str, connection: Connection<M>, last_seen: Option<u64>) -> Result<(Membership<M, D>, Resume)> where D: Default {
+            let permit = {
+                let this = broadcaster.read().await;
+                let quota = this.config.get().quota;
+                let key = this.quota_key.get().0.and_then(|key| key(&connection));
+
+                this.quotas.acquire(key, quota.total, quota.per_key)
+            };
+
+            let permit = match permit {
+                Ok(permit) => permit,
+                Err(error) => {
+                    let config = broadcaster.read().await.config.get();
+                    let _ = connection.close(config.quota.close_frame, config.close_timeout).await;
+
+                    return Err(error);
+                }
+            };
+
+            // the clones of the connection in the rooms share the permit, so it's counted as long as it's in any of them, even after the membership is detached.
+            let _ = connection.permit.set(permit);
+
+            let resume = loop {
+                let (handle, mut room) = loop {
+                    let handle = {
+                        let mut this = broadcaster.write().await;
+
+                        this.spawn_heartbeat(Arc::downgrade(broadcaster));
+                        this.spawn_reaper(Arc::downgrade(broadcaster));
+                        this.handle_room(room_id)
+                    };
+
+                    let room = Arc::clone(&handle).write_owned().await;
+
+                    // the room can be removed while we wait for it's lock, than it has to be created again.
+                    if !room.removed {
+                        break (handle, room);
+                    }
+                };
+
+                let (promoted, timeout) = match (room.admit(connection.clone(), last_seen), room.config.get().capacity_policy) {
+                    (Err(Error::RoomFull(_)), CapacityPolicy::Waitlist(timeout)) => (room.wait(connection.clone(), last_seen), timeout),
+                    (admission, _) => break admission?
+                };
+
+                drop(room);
+
+                // if this future is dropped while waiting, such as when the client disconnects, the waiter is taken off the waitlist by the drop of it.
+                let mut waiting = Waiting {
+                    room: handle,
+                    id: connection.id.clone(),
+                    failure: connection.failure.clone(),
+                    promoted,
+                    settled: false
+                };
+
+                match tokio::time::timeout(timeout, &mut waiting.promoted).await {
+                    Ok(Ok(admission)) => {
+                        waiting.settled = true;
+
+                        break admission?;
+                    },
+                    // the room is removed while waiting, it's created again.
+                    Ok(Err(_)) => {
+                        waiting.settled = true;
+
+                        continue;
+                    },
+                    Err(_) => match waiting.leave().await {
+                        // it can be promoted right before the timeout.
+                        Some(admission) => break admission?,
+                        None => return Err(Error::RoomFull(room_id.to_string()))
+                    }
+                }
+            };
+
+            let membership = Membership {
+                broadcaster: Arc::clone(broadcaster),
+                room_id: room_id.to_string(),
+                connection,
+                released: false
+            };
+
+            Ok((membership, resume))
+        }
+
+        /// count the connections by the key which given callback gives, for the `per_key` quota of the config. `None` counts them only in the total.
+        pub fn set_quota_key(&self, key: Option<QuotaKey<M>>) {
+            self.quota_key.set(Keyer(key));
+        }
+
+        /// how many connections the broadcaster took through `.handle()` and `.serve()`, which are not dropped yet.
+        pub fn total_connections(&self) -> usize {
+            self.quotas.total()
+        }
+
+        /// how many of those connections have given quota key.
+        pub fn connections_by_key(&self, key: &str) -> usize {
+            self.quotas.count(key)
+        }
+
+        /// check every connection which joins a room with given callback, before it's put into the room. `None` lets everyone in.
+        pub fn set_admission(&self, check: Option<Admission<M, D>>) {
+            self.admission.set(Gate(check));
+        }
+
+        /// check if a room with given id exist and if it's not create one. It returns the handle of the room, lock it for using the room:
+        pub fn handle_room(&mut self, id: &str) -> RoomHandle<M, D> where D: Default {
+            self.room_entry(id, false, RoomState::default)
+        }
+
+        /// create a room with given state. If a room with that id already exists, `Error::DuplicateRoom` is returned and it's state is not touched. The room isn't removed when it's last member leaves, only by `.remove_room()`, `.remove_empty_rooms()` or the reaper when it expires.
+        pub fn create_room(&mut self, id: &str, state: RoomState<D>) -> Result<RoomHandle<M, D>> {
+            match self.rooms.contains_key(id) {
+                true => Err(Error::DuplicateRoom(id.to_string())),
+                false => Ok(self.room_entry(id, true, || state))
+            }
+        }
+
+        /// get the room with given id, creating it with the state which is given by the closure if it doesn't exist.
+        fn room_entry<F>(&mut self, id: &str, persistent: bool, state: F) -> RoomHandle<M, D> where F: FnOnce() -> RoomState<D> {
+            let mut created = false;
+
+            let room = self.rooms.entry(id.to_string()).or_insert_with(|| Arc::new_cyclic(|this| {
+                created = true;
+
+                RwLock::new(Room {
+                    id: id.to_string(),
+                    connections: HashMap::new(),
+                    count: 0,
+                    index: self.index.clone(),
+                    config: self.config.clone(),
+                    presence: self.presence.clone(),
+                    admission: self.admission.clone(),
+                    this: this.clone(),
+                    removed: false,
+                    persistent,
+                    state: state(),
+                    backpressure: None,
+                    empty_since: Some(Instant::now()),
+                    waitlist: VecDeque::new(),
+                    history: Backlog::default(),
+                    order: Mutex::new(())
+                })
+            }));
+
+            let room = Arc::clone(room);
+
+            if created {
+                crate::emit(&self.config.get().on_event, || Event::RoomCreated { room: id.to_string() });
+            }
+
+            room
+        }
+
+        /// Get the handle of the room with given id. If the room doesn't exist, `Error::UnknownRoom` is returned. Get it in it's own statement, so the broadcaster is unlocked before you lock the room:
+        ///
+        /// ```rust
+        ///
+        /// use axum_wsb::normal::Broadcaster;
+        /// use tokio::sync::RwLock;
+        /// use std::sync::Arc;
+        ///
+        /// fn main () {
+        ///     let receivers: Arc<RwLock<Broadcaster>> = Broadcaster::new();
+        ///
+        ///     async {
+        ///         let room = receivers.read().await.room(&"room".to_string());
+        ///
+        ///         if let Ok(room) = room {
+        ///             room.read().await.broadcast(&"hello".into()).await;
+        ///         }
+        ///     };
+        /// }
+        ///
+        /// ```
+        pub fn room(&self, id: &String) -> Result<RoomHandle<M, D>> {
+            self.rooms.get(id).cloned().ok_or_else(|| Error::UnknownRoom(id.clone()))
+        }
+
+        /// iterate through the handles of the rooms, in no particular order.
+        pub fn rooms(&self) -> impl Iterator<Item = &RoomHandle<M, D>> {
+            self.rooms.values()
+        }
+
+        /// iterates through every room and does something with them immutably. You cannot mutate anything inside of it, even rooms and not captured variables.
+        ///
+        /// ```rust
+        ///
+        /// use axum_wsb::normal::Broadcaster;
+        /// use tokio::sync::RwLock;
+        /// use std::sync::Arc;
+        ///
+        /// fn main () {
+        ///     let receivers: Arc<RwLock<Broadcaster>> = Broadcaster::new();
+        ///
+        ///     async {
+        ///         receivers.read().await.each_room_immut(|room| println!("hello, {}. guest!", room.id)).await;
+        ///     };
+        ///
+        /// }
+        ///
+        ///
+        /// ```
+        pub async fn each_room_immut<F>(&self, f: F) where F: Fn(&Room<M, D>) {
+            for room in self.rooms.values() {
+                f(&*room.read().await);
+            }
+        }
+
+        /// iterates through every room and does something with them immutably. You cannot mutate rooms itself but can mutate captured variables.
+        ///
+        /// ```rust
+        ///
+        /// use axum_wsb::normal::Broadcaster;
+        /// use tokio::sync::RwLock;
+        /// use std::sync::Arc;
+        ///
+        /// fn main () {
+        ///     let receivers: Arc<RwLock<Broadcaster>> = Broadcaster::new();
+        ///
+        ///     let mut num = 0;
+        ///
+        ///     async {
+        ///         receivers.read().await.each_room(|room| {
+        ///             num = num + 1;
+        ///         }).await;
+        ///     };
+        ///
+        ///
+        ///     println!("here is number: {}", num)
+        /// }
+        ///
+        ///
+        /// ```
+        pub async fn each_room<F>(&self, mut f: F) where F: FnMut(&Room<M, D>) {
+            for room in self.rooms.values() {
+                f(&*room.read().await);
+            }
+        }
+
+        /// iterates through every room and does something with them mutably. You can mutate everything belong to it. But warning, for now, you cannot send messages to client from it right now and until async closures will be stable probably we're not be able to do it. Because of that, we're not able to give examples for that.
+        pub async fn each_room_mut<F>(&self, mut f: F) where F: FnMut(&mut Room<M, D>) {
+            for room in self.rooms.values() {
+                f(&mut *room.write().await);
+            }
+        }
+
+        /// check if a room with given id exist and wrap it's handle in an option.
+        pub fn check_room(&self, id: &String) -> Option<RoomHandle<M, D>> {
+            self.rooms.get(id).cloned()
+        }
+
+        /// only check if a room exist and if it's return true.
+        pub fn check(&self, id: &String) -> bool {
+            self.rooms.contains_key(id)
+        }
+
+        /// it removes a room with given id and closes all the connections inside of it with the close frame in the config. The broadcaster is only locked while the room is taken out, so the close handshakes don't hold up the other rooms:
+        pub async fn remove_room(broadcaster: &Arc<RwLock<Self>>, id: &String) -> Result<CloseReport> {
+            let (connections, config) = {
+                let mut this = broadcaster.write().await;
+                let room = this.rooms.remove(id).ok_or_else(|| Error::UnknownRoom(id.clone()))?;
+                let mut room = room.write().await;
+
+                room.removed = true;
+                room.waitlist.clear();
+
+                (room.take_where(|_| true), this.config.get())
+            };
+
+            crate::emit(&config.on_event, || Event::RoomRemoved { room: id.clone() });
+
+            Ok(close_all(connections, config.close_frame, config.close_timeout).await)
+        }
+
+        /// remove the room with given id if it has no connections.
+        async fn remove_if_empty(&mut self, id: &String) {
+            let Some(room) = self.check_room(id) else {
+                return;
+            };
+
+            let mut room = room.write().await;
+
+            if room.is_empty() {
+                room.removed = true;
+                room.waitlist.clear();
+                self.rooms.remove(id);
+
+                crate::emit(&self.config.get().on_event, || Event::RoomRemoved { room: id.clone() });
+            }
+        }
+
+        /// it removes all empty rooms. Rooms which are locked at that moment are in use, so they're kept.
+        pub fn remove_empty_rooms(&mut self) {
+            let hook = self.config.get().on_event;
+
+            self.rooms.retain(|id, room| match room.try_write() {
+                Ok(mut room) if room.is_empty() => {
+                    room.removed = true;
+                    room.waitlist.clear();
+
+                    crate::emit(&hook, || Event::RoomRemoved { room: id.clone() });
+
+                    false
+                },
+                _ => true
+            });
+        }
+
+        /// Removes the connections with given id from every room they're in and gives them back, each of them once. Use `.close()` on the returned connections for closing them gracefully, dropping them only lets their writer tasks deliver the queued messages and than drop the receivers without any close frame.
+        pub async fn remove_connection(&self, id: &String) -> Vec<Connection<M>> {
+            let mut removed: Vec<Connection<M>> = vec![];
+
+            for room_id in self.index.rooms_of(id) {
+                let Some(room) = self.check_room(&room_id) else {
+                    continue;
+                };
+
+                for connection in room.write().await.take(id) {
+                    if !removed.iter().any(|removed| removed.is(&connection)) {
+                        removed.push(connection);
+                    }
+                }
+            }
+
+            removed
+        }
+
+        /// join the connections with given id to the room with given id too, creating the room if it doesn't exist. They're gathered from every room they're in, so connections which share an id but are in different rooms all join. They keep their sockets, so they get the messages of every room they're in.
+        pub async fn join(&mut self, conn_id: &str, room_id: &str) -> Result<()> where D: Default {
+            let connections = self.find(conn_id).await;
+
+            if connections.is_empty() {
+                return Err(Error::UnknownConnection(conn_id.to_string()));
+            }
+
+            let room = self.handle_room(room_id);
+            let mut room = room.write().await;
+
+            for (_, _, connection) in connections {
+                room.join(connection)?;
+            }
+
+            Ok(())
+        }
+
+        /// take the connections with given id out of the room with given id only and give them back. They stay in their other rooms, if they're in none of them anymore, close the returned connections.
+        pub async fn leave(&self, conn_id: &String, room_id: &String) -> Result<Vec<Connection<M>>> {
+            let room = self.room(room_id)?;
+            let connections = room.write().await.take(conn_id);
+
+            match connections.is_empty() {
+                true => Err(Error::UnknownConnection(conn_id.clone())),
+                false => Ok(connections)
+            }
+        }
+
+        /// ids of the rooms which the connection with given id is in, in no particular order.
+        pub fn rooms_of(&self, conn_id: &str) -> Vec<String> {
+            self.index.rooms_of(conn_id)
+        }
+
+        /// change the metadata of the connections with given id and announce it to the other members of every room they're in. If there is no such connection, `Error::UnknownConnection` is returned.
+        pub async fn update_meta(&self, conn_id: &str, meta: M) -> Result<()> {
+            let meta = Arc::new(meta);
+            let mut updated = false;
+
+            for room_id in self.index.rooms_of(conn_id) {
+                let Some(room) = self.rooms.get(&room_id) else {
+                    continue;
+                };
+
+                let room = room.read().await;
+
+                for connection in room.connections.get(conn_id).into_iter().flatten() {
+                    connection.meta.set(Arc::clone(&meta));
+                    room.announce(PresenceKind::Updated, connection);
+                    updated = true;
+                }
+            }
+
+            match updated {
+                true => Ok(()),
+                false => Err(Error::UnknownConnection(conn_id.to_string()))
+            }
+        }
+
+        /// send the text message to the connections with given id, whatever room they're in. Connections which share an id all get it and a connection which is in many rooms gets it once. It waits for the queues by the backpressure policy of the config, a dropped message is returned as `Error::QueueFull`. The broadcaster is only locked while the connections are found.
+        pub async fn send_to(broadcaster: &Arc<RwLock<Self>>, conn_id: &str, message: &Utf8Bytes) -> Result<()> {
+            Self::deliver_to(broadcaster, [conn_id], Message::Text(message.clone())).await.into_result()
+        }
+
+        /// send the text message to the connections with given ids, whatever room they're in, and report which of them took it. Ids which have no connection are reported in `failed`.
+        pub async fn send_to_many<I>(broadcaster: &Arc<RwLock<Self>>, ids: I, message: &Utf8Bytes) -> DeliveryReport where I: IntoIterator, I::Item: AsRef<str> {
+            Self::deliver_to(broadcaster, ids, Message::Text(message.clone())).await
+        }
+
+        /// send the raw binary bytes to the connections with given id, like `.send_to()`.
+        pub async fn binary_to(broadcaster: &Arc<RwLock<Self>>, conn_id: &str, bytes: &Bytes) -> Result<()> {
+            Self::deliver_to(broadcaster, [conn_id], Message::Binary(bytes.clone())).await.into_result()
+        }
+
+        /// send the raw binary bytes to the connections with given ids, like `.send_to_many()`.
+        pub async fn binary_to_many<I>(broadcaster: &Arc<RwLock<Self>>, ids: I, bytes: &Bytes) -> DeliveryReport where I: IntoIterator, I::Item: AsRef<str> {
+            Self::deliver_to(broadcaster, ids, Message::Binary(bytes.clone())).await
+        }
+
+        /// find the connections with given id in every room they're in, with the room they're found in. A connection which is in many rooms is given once.
+        async fn find(&self, conn_id: &str) -> Vec<Target<M, D>> {
+            let mut found: Vec<Target<M, D>> = vec![];
+            let mut seen = HashSet::new();
+
+            for room_id in self.index.rooms_of(conn_id) {
+                let Some(room) = self.rooms.get(&room_id) else {
+                    continue;
+                };
+
+                for connection in room.read().await.connections.get(conn_id).into_iter().flatten() {
+                    if seen.insert(Arc::as_ptr(&connection.liveness) as usize) {
+                        found.push((room_id.clone(), Arc::downgrade(room), connection.clone()));
+                    }
+                }
+            }
+
+            found
+        }
+
+        /// push the message into the queues of the connections with given ids concurrently, by the backpressure policy of the config, and report what happened for each of them. An id which has no connection is reported in `failed` with `Error::UnknownConnection`.
+        async fn deliver_to<I>(broadcaster: &Arc<RwLock<Self>>, ids: I, message: Message) -> DeliveryReport where I: IntoIterator, I::Item: AsRef<str> {
+            // the broadcaster is only locked while the connections are found, it's unlocked before waiting for their queues.
+            let (targets, unknown, config) = {
+                let this = broadcaster.read().await;
+                let mut targets: Vec<Target<M, D>> = vec![];
+                let mut unknown = vec![];
+                let mut seen = HashSet::new();
+
+                for id in ids {
+                    let id = id.as_ref();
+
+                    // connections are found by their id, so a repeated id is the only way of finding one twice.
+                    if !seen.insert(id.to_string()) {
+                        continue;
+                    }
+
+                    let found = this.find(id).await;
+
+                    if found.is_empty() {
+                        unknown.push((id.to_string(), Error::UnknownConnection(id.to_string())));
+                    }
+
+                    targets.extend(found);
+                }
+
+                (targets, unknown, this.config.get())
+            };
+
+            let mut report = fan_out(queue_all(targets.iter().map(|(room_id, room, connection)| (room, room_id.as_str(), connection)), message, &config.backpressure, &config), &config).await;
+
+            report.failed.extend(unknown);
+
+            report
+        }
+    }
+
+    /// data message which `.serve()` gives to it's handler.
+    #[derive(Debug, Clone)]
+    pub enum Incoming {
+        Text(Utf8Bytes),
+        Binary(Bytes)
+    }
+
+    /// what the handler of `.serve()` gets with each message: the broadcaster, id of the room which the connection joined and the connection itself, for answering it.
+    #[derive(Debug)]
+    pub struct Session<M = (), D = ()> {
+        pub broadcaster: Arc<RwLock<Broadcaster<M, D>>>,
+        pub room_id: String,
+        pub connection: Connection<M>
+    }
+
+    impl<M, D> Clone for Session<M, D> {
+        fn clone(&self) -> Self {
+            Self {
+                broadcaster: Arc::clone(&self.broadcaster),
+                room_id: self.room_id.clone(),
+                connection: self.connection.clone()
+            }
+        }
+    }
+
+    impl<M, D> Session<M, D> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        /// get the handle of the room which the connection joined.
+        pub async fn room(&self) -> Result<RoomHandle<M, D>> {
+            self.broadcaster.read().await.room(&self.room_id)
+        }
+    }
+
+    /// membership of a connection, which is returned from `Broadcaster::handle()`. It can be used like the broadcaster itself. When it's dropped, such as when the handler of the socket returns or panics,
+    /// the connection is removed from every room it's in and the rooms which become empty are removed too, unless the reaper has an `empty_ttl` or they're created by `.create_room()`. A connection which replaced it with the same id is not touched.
+    #[must_use = "the connection is removed from it's rooms as soon as the membership is dropped"]
+    #[derive(Debug)]
+    pub struct Membership<M = (), D = ()> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        broadcaster: Arc<RwLock<Broadcaster<M, D>>>,
+        room_id: String,
+        connection: Connection<M>,
+        released: bool
+    }
+
+    impl<M, D> Membership<M, D> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        /// id of the connection.
+        pub fn id(&self) -> &str {
+            &self.connection.id
+        }
+
+        /// the connection itself, such as for recording the pongs which your read loop receives.
+        pub fn connection(&self) -> &Connection<M> {
+            &self.connection
+        }
+
+        /// id of the room which the connection joined with `.handle()`.
+        pub fn room_id(&self) -> &str {
+            &self.room_id
+        }
+
+        /// remove the connection from it's rooms now and wait until it's done, instead of leaving it to the drop.
+        pub async fn release(mut self) {
+            self.released = true;
+
+            release(&self.broadcaster, &self.room_id, &self.connection.id, &self.connection.failure).await;
+        }
+
+        /// give up the membership without removing the connection, it stays in it's rooms until you remove it by yourself.
+        pub fn detach(mut self) -> Arc<RwLock<Broadcaster<M, D>>> {
+            self.released = true;
+
+            Arc::clone(&self.broadcaster)
+        }
+    }
+
+    impl<M, D> Deref for Membership<M, D> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        type Target = Arc<RwLock<Broadcaster<M, D>>>;
+
+        fn deref(&self) -> &Self::Target {
+            &self.broadcaster
+        }
+    }
+
+    impl<M, D> Drop for Membership<M, D> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        fn drop(&mut self) {
+            if self.released {
+                return;
+            }
+
+            // locks can't be waited in drop, so the cleanup runs in the background. If there is no runtime anymore, there is nothing left to clean either.
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let broadcaster = Arc::clone(&self.broadcaster);
+                let room_id = self.room_id.clone();
+                let id = self.connection.id.clone();
+                let failure = self.connection.failure.clone();
+
+                runtime.spawn(async move {
+                    release(&broadcaster, &room_id, &id, &failure).await;
+                });
+            }
+        }
+    }
+
+    /// a connection's place on the waitlist of a room, while `.handle()` waits for it to be promoted. The waiter keeps a clone of the connection, so if it's dropped before it's promoted, the waiter is taken off the waitlist for not keeping the socket and it's quota alive. If it's promoted in the meantime, it's seat is freed.
+    struct Waiting<M, D> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        room: RoomHandle<M, D>,
+        id: String,
+        failure: watch::Receiver<Option<Error>>,
+        promoted: oneshot::Receiver<Result<Resume>>,
+        settled: bool
+    }
+
+    impl<M, D> Waiting<M, D> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        /// stop waiting and take the waiter off the waitlist. The admission is returned if it's promoted right before that.
+        async fn leave(mut self) -> Option<Result<Resume>> {
+            self.settled = true;
+            self.promoted.close();
+
+            if let Ok(admission) = self.promoted.try_recv() {
+                return Some(admission);
+            }
+
+            self.room.write().await.waitlist.retain(|waiter| !waiter.promoted.is_closed());
+
+            None
+        }
+    }
+
+    impl<M, D> Drop for Waiting<M, D> where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        fn drop(&mut self) {
+            if self.settled {
+                return;
+            }
+
+            self.promoted.close();
+
+            let admitted = matches!(self.promoted.try_recv(), Ok(Ok(_)));
+
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let room = Arc::clone(&self.room);
+                let id = self.id.clone();
+                let failure = self.failure.clone();
+
+                runtime.spawn(async move {
+                    let mut room = room.write().await;
+
+                    if admitted {
+                        room.evict(&id, &failure);
+                    }
+
+                    room.waitlist.retain(|waiter| !waiter.promoted.is_closed());
+                });
+            }
+        }
+    }
+
+    /// remove the connection which has given writer from every room it's in, than remove the rooms which became empty. If the reaper has an `empty_ttl`, emptied rooms are left to it, and rooms which are created by `.create_room()` are always kept.
+    async fn release<M, D>(broadcaster: &Arc<RwLock<Broadcaster<M, D>>>, room_id: &String, id: &String, failure: &watch::Receiver<Option<Error>>) where M: Send + Sync + 'static, D: Send + Sync + 'static {
+        let (rooms, prune): (Vec<RoomHandle<M, D>>, bool) = {
+            let broadcaster = broadcaster.read().await;
+            let prune = !broadcaster.config.get().reaper.is_some_and(|reaper| reaper.empty_ttl.is_some());
+            let mut room_ids = broadcaster.index.rooms_of(id);
+
+            if !room_ids.contains(room_id) {
+                room_ids.push(room_id.clone());
+            }
+
+            (room_ids.iter().filter_map(|room_id| broadcaster.check_room(room_id)).collect(), prune)
+        };
+
+        let mut emptied = vec![];
+
+        for room in rooms {
+            let mut room = room.write().await;
+
+            room.evict(id, failure);
+
+            if prune && !room.persistent && room.is_empty() {
+                emptied.push(room.id.clone());
+            }
+        }
+
+        if emptied.is_empty() {
+            return;
+        }
+
+        let mut broadcaster = broadcaster.write().await;
+
+        for room_id in emptied {
+            broadcaster.remove_if_empty(&room_id).await;
+        }
+    }
+
+    impl<M, D> Default for Broadcaster<M, D> {
+        fn default() -> Self {
+            Self {
+                rooms: HashMap::new(),
+                index: Index::default(),
+                config: Shared::default(),
+                presence: Shared::default(),
+                admission: Shared::default(),
+                quota_key: Shared::default(),
+                rate_limit: Shared::default(),
+                quotas: Quotas::default(),
+                heartbeat: None,
+                reaper: None
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use futures_util::sink;
+        use std::{io, pin::Pin};
+
+        type Written = Arc<Mutex<Vec<Message>>>;
+        type MockSink = Pin<Box<dyn Sink<Message, Error = io::Error> + Send>>;
+
+        /// a sink which keeps what's written to it.
+        fn recording() -> (MockSink, Written) {
+            let written = Written::default();
+
+            let sink = sink::unfold(Arc::clone(&written), |written, message: Message| async move {
+                written.lock().unwrap().push(message);
+
+                Ok::<_, io::Error>(written)
+            });
+
+            (Box::pin(sink), written)
+        }
+
+        /// a sink which never finishes a write, like a client which stopped reading.
+        fn stalled() -> MockSink {
+            Box::pin(sink::unfold((), |_, _: Message| std::future::pending::<io::Result<()>>()))
+        }
+
+        fn connection(id: &str, sink: MockSink, capacity: usize) -> Connection {
+            Connection::spawn(id.to_string(), sink, (), capacity)
+        }
+
+        fn texts(written: &Written) -> Vec<String> {
+            written.lock().unwrap().iter().filter_map(|message| match message {
+                Message::Text(text) => Some(text.to_string()),
+                _ => None
+            }).collect()
+        }
+
+        /// let the writer tasks run.
+        async fn let_writers_run() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn sequenced_broadcasts_dont_wait_for_each_other() {
+            let send_timeout = Duration::from_millis(300);
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config { send_timeout, ..Default::default() });
+            let room = broadcaster.write().await.handle_room("room");
+            let (sink, written) = recording();
+            let slow = connection("slow", stalled(), 1);
+
+            // the writer gets stuck with the first message and the second one fills the queue.
+            slow.enqueue(Message::Text("first".into())).unwrap();
+            let_writers_run().await;
+            slow.enqueue(Message::Text("second".into())).unwrap();
+
+            room.write().await.join(connection("healthy", sink, DEFAULT_QUEUE_CAPACITY)).unwrap();
+            room.write().await.join(slow).unwrap();
+
+            let room = room.read().await;
+            let started = Instant::now();
+
+            let reports = tokio::join!(
+                room.broadcast_sequenced(|sequence| sequence.to_string().into()),
+                room.broadcast_sequenced(|sequence| sequence.to_string().into()),
+                room.broadcast_sequenced(|sequence| sequence.to_string().into()),
+                room.broadcast_sequenced(|sequence| sequence.to_string().into())
+            );
+
+            assert!(started.elapsed() < send_timeout * 2);
+
+            for report in [reports.0, reports.1, reports.2, reports.3] {
+                assert_eq!(report.delivered, ["healthy"]);
+                assert!(matches!(&report.failed[..], [(id, Error::Timeout(_))] if id == "slow"));
+            }
+
+            let_writers_run().await;
+
+            assert_eq!(texts(&written), ["1", "2", "3", "4"]);
+        }
+        #[tokio::test(start_paused = true)]
+        async fn reaper_keeps_the_persistent_and_expiring_rooms() {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config {
+                reaper: Some(Reaper { interval: Duration::from_secs(1), empty_ttl: Some(Duration::from_secs(5)), close_frame: None }),
+                ..Default::default()
+            });
+
+            {
+                let mut this = broadcaster.write().await;
+
+                this.create_room("persistent", RoomState::default()).unwrap();
+                this.create_room("expiring", RoomState { expires_at: Some(SystemTime::now() + Duration::from_secs(3600)), ..RoomState::default() }).unwrap();
+                this.handle_room("stale");
+            }
+
+            Broadcaster::start_reaper(&broadcaster).await;
+            tokio::time::sleep(Duration::from_secs(10)).await;
+
+            let this = broadcaster.read().await;
+
+            assert!(this.check(&"persistent".to_string()));
+            assert!(this.check(&"expiring".to_string()));
+            assert!(!this.check(&"stale".to_string()));
+        }
+        /// a broadcaster whose room has one seat and a waitlist.
+        async fn waitlisted(timeout: Duration) -> (Arc<RwLock<Broadcaster>>, RoomHandle) {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config { capacity_policy: CapacityPolicy::Waitlist(timeout), ..Default::default() });
+            let room = broadcaster.write().await.create_room("room", RoomState { capacity: Some(1), ..RoomState::default() }).unwrap();
+
+            (broadcaster, room)
+        }
+
+        /// start waiting on the waitlist of the room in the background.
+        async fn wait_for_seat(broadcaster: &Arc<RwLock<Broadcaster>>, id: &str) -> tokio::task::JoinHandle<Result<Membership>> {
+            let broadcaster = Arc::clone(broadcaster);
+            let connection = connection(id, recording().0, DEFAULT_QUEUE_CAPACITY);
+            let waiting = tokio::spawn(async move { Broadcaster::enter(&broadcaster, "room", connection, None).await.map(|(membership, _)| membership) });
+
+            let_writers_run().await;
+
+            waiting
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn waitlist_promotes_when_a_seat_is_freed() {
+            let (broadcaster, room) = waitlisted(Duration::from_secs(10)).await;
+            let (first, _) = Broadcaster::enter(&broadcaster, "room", connection("first", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let waiting = wait_for_seat(&broadcaster, "second").await;
+
+            assert_eq!(room.read().await.waiting(), 1);
+
+            drop(first);
+
+            let second = waiting.await.unwrap().unwrap();
+            let room = room.read().await;
+
+            assert_eq!(second.id(), "second");
+            assert_eq!(room.waiting(), 0);
+            assert_eq!(room.members().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), ["second"]);
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn waitlist_times_out() {
+            let (broadcaster, room) = waitlisted(Duration::from_secs(1)).await;
+            let (_first, _) = Broadcaster::enter(&broadcaster, "room", connection("first", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let waiting = wait_for_seat(&broadcaster, "second").await;
+
+            assert!(matches!(waiting.await.unwrap(), Err(Error::RoomFull(id)) if id == "room"));
+            assert_eq!(room.read().await.waiting(), 0);
+            assert_eq!(broadcaster.read().await.total_connections(), 1);
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn waitlist_forgets_a_dropped_waiter() {
+            let (broadcaster, room) = waitlisted(Duration::from_secs(10)).await;
+            let (_first, _) = Broadcaster::enter(&broadcaster, "room", connection("first", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let waiting = wait_for_seat(&broadcaster, "second").await;
+
+            assert_eq!(broadcaster.read().await.total_connections(), 2);
+
+            // like the client disconnecting while it waits.
+            waiting.abort();
+            let_writers_run().await;
+
+            assert_eq!(room.read().await.waitlist.len(), 0);
+            assert_eq!(broadcaster.read().await.total_connections(), 1);
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn waitlist_promotion_is_checked_by_the_admission() {
+            let (broadcaster, room) = waitlisted(Duration::from_secs(10)).await;
+            let (first, _) = Broadcaster::enter(&broadcaster, "room", connection("first", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let waiting = wait_for_seat(&broadcaster, "second").await;
+
+            broadcaster.read().await.set_admission(Some(Arc::new(|_, connection| connection.id != "second")));
+            drop(first);
+
+            assert!(matches!(waiting.await.unwrap(), Err(Error::Refused(id)) if id == "second"));
+            assert!(room.read().await.is_empty());
+        }
+        fn closed(written: &Written) -> bool {
+            written.lock().unwrap().iter().any(|message| matches!(message, Message::Close(_)))
+        }
+
+        /// join two connections with the same id to the room by given policy, than close the first one by itself like the examples do when it's client closes the socket.
+        async fn close_the_first_of_duplicates(policy: DuplicatePolicy) -> (RoomHandle, Written, Written) {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config { duplicate_policy: policy, ..Default::default() });
+            let (first_sink, first) = recording();
+            let (second_sink, second) = recording();
+            let (membership, _) = Broadcaster::enter(&broadcaster, "room", connection("device", first_sink, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let (_second, _) = Broadcaster::enter(&broadcaster, "room", connection("device", second_sink, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+
+            let _ = membership.connection().clone().close(None, Duration::from_secs(1)).await;
+
+            membership.release().await;
+
+            let room = broadcaster.read().await.room(&"room".to_string()).unwrap();
+
+            room.read().await.broadcast(&"hello".into()).await;
+            let_writers_run().await;
+
+            (room, first, second)
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn closing_a_replaced_connection_keeps_the_new_one() {
+            let (room, first, second) = close_the_first_of_duplicates(DuplicatePolicy::Replace(None)).await;
+
+            assert!(closed(&first));
+            assert!(!closed(&second));
+            assert_eq!(texts(&second), ["hello"]);
+            assert_eq!(room.read().await.len(), 1);
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn closing_one_of_the_kept_connections_keeps_the_other() {
+            let (room, first, second) = close_the_first_of_duplicates(DuplicatePolicy::KeepBoth).await;
+
+            assert!(closed(&first));
+            assert!(texts(&first).is_empty());
+            assert!(!closed(&second));
+            assert_eq!(texts(&second), ["hello"]);
+            assert_eq!(room.read().await.len(), 1);
+        }
+        #[tokio::test(start_paused = true)]
+        async fn send_to_reaches_each_connection_once() {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::new();
+            let (first_sink, first) = recording();
+            let (second_sink, second) = recording();
+            let (_first, _) = Broadcaster::enter(&broadcaster, "one", connection("first", first_sink, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let (_second, _) = Broadcaster::enter(&broadcaster, "one", connection("second", second_sink, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+
+            broadcaster.write().await.join("first", "two").await.unwrap();
+
+            assert!(Broadcaster::send_to(&broadcaster, "first", &"hello".into()).await.is_ok());
+            assert!(matches!(Broadcaster::send_to(&broadcaster, "missing", &"hello".into()).await, Err(Error::UnknownConnection(id)) if id == "missing"));
+
+            let report = Broadcaster::send_to_many(&broadcaster, ["first", "second", "missing", "first"], &"everyone".into()).await;
+
+            let_writers_run().await;
+
+            assert_eq!(report.delivered.len(), 2);
+            assert!(report.delivered.contains(&"first".to_string()) && report.delivered.contains(&"second".to_string()));
+            assert!(matches!(&report.failed[..], [(id, Error::UnknownConnection(_))] if id == "missing"));
+            assert_eq!(texts(&first), ["hello", "everyone"]);
+            assert_eq!(texts(&second), ["everyone"]);
+        }
+        #[tokio::test(start_paused = true)]
+        async fn join_takes_the_connections_from_every_room() {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config { duplicate_policy: DuplicatePolicy::KeepBoth, ..Default::default() });
+            let (phone_sink, phone) = recording();
+            let (laptop_sink, laptop) = recording();
+            let (_phone, _) = Broadcaster::enter(&broadcaster, "one", connection("device", phone_sink, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let (_laptop, _) = Broadcaster::enter(&broadcaster, "two", connection("device", laptop_sink, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+
+            broadcaster.write().await.join("device", "three").await.unwrap();
+            broadcaster.write().await.join("device", "three").await.unwrap();
+
+            let room = broadcaster.read().await.room(&"three".to_string()).unwrap();
+            let room = room.read().await;
+
+            room.broadcast(&"hello".into()).await;
+            let_writers_run().await;
+
+            assert_eq!(room.len(), 2);
+            assert_eq!(texts(&phone), ["hello"]);
+            assert_eq!(texts(&laptop), ["hello"]);
+        }
+        #[tokio::test(start_paused = true)]
+        async fn dropping_the_membership_prunes_the_room() {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::new();
+            let (membership, _) = Broadcaster::enter(&broadcaster, "room", connection("first", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+
+            assert_eq!(broadcaster.read().await.rooms_of("first"), ["room"]);
+
+            drop(membership);
+            let_writers_run().await;
+
+            let this = broadcaster.read().await;
+
+            assert!(!this.check(&"room".to_string()));
+            assert!(this.rooms_of("first").is_empty());
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn replacing_connection_survives_the_old_membership() {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config { duplicate_policy: DuplicatePolicy::Replace(None), ..Default::default() });
+            let (old, _) = Broadcaster::enter(&broadcaster, "room", connection("device", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let (_new, _) = Broadcaster::enter(&broadcaster, "room", connection("device", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+
+            drop(old);
+            let_writers_run().await;
+
+            let this = broadcaster.read().await;
+            let room = this.room(&"room".to_string()).unwrap();
+
+            assert_eq!(room.read().await.len(), 1);
+            assert_eq!(this.rooms_of("device"), ["room"]);
+        }
+        #[tokio::test(start_paused = true)]
+        async fn presence_is_announced_to_the_other_members() {
+            let broadcaster: Arc<RwLock<Broadcaster<String>>> = Broadcaster::new();
+            let (host_sink, host) = recording();
+            let (guest_sink, guest) = recording();
+
+            broadcaster.read().await.set_presence(Some(Arc::new(|event| Some(format!("{:?} {} {} {}", event.kind, event.room, event.id, event.meta).into()))));
+
+            let (_host, _) = Broadcaster::enter(&broadcaster, "room", Connection::spawn("host".to_string(), host_sink, "Ada".to_string(), DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+            let (membership, _) = Broadcaster::enter(&broadcaster, "room", Connection::spawn("guest".to_string(), guest_sink, "Grace".to_string(), DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+
+            membership.release().await;
+            let_writers_run().await;
+
+            assert_eq!(texts(&host), ["Joined room guest Grace", "Left room guest Grace"]);
+            assert!(texts(&guest).is_empty());
+        }
+        #[tokio::test(start_paused = true)]
+        async fn events_come_in_order() {
+            let events: Arc<Mutex<Vec<Event>>> = Arc::default();
+            let hook = Arc::clone(&events);
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config { on_event: Some(Arc::new(move |event| hook.lock().unwrap().push(event.clone()))), ..Default::default() });
+            let (membership, _) = Broadcaster::enter(&broadcaster, "room", connection("first", recording().0, DEFAULT_QUEUE_CAPACITY), None).await.unwrap();
+
+            membership.release().await;
+
+            let events: Vec<String> = events.lock().unwrap().iter().map(|event| format!("{:?}", event)).collect();
+
+            assert_eq!(events, [
+                r#"RoomCreated { room: "room" }"#,
+                r#"Joined { room: "room", connection: "first" }"#,
+                r#"Left { room: "room", connection: "first" }"#,
+                r#"RoomEmptied { room: "room" }"#,
+                r#"RoomRemoved { room: "room" }"#
+            ]);
+        }
+        #[tokio::test(start_paused = true)]
+        async fn close_reports_the_unclean_connections() {
+            let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::new();
+            let room = broadcaster.write().await.handle_room("room");
+            let (sink, written) = recording();
+            let dead = Box::pin(sink::unfold((), |_, _: Message| async { Err::<(), _>(io::Error::from(io::ErrorKind::BrokenPipe)) }));
+
+            room.write().await.join(connection("healthy", sink, DEFAULT_QUEUE_CAPACITY)).unwrap();
+            room.write().await.join(connection("dead", dead, DEFAULT_QUEUE_CAPACITY)).unwrap();
+
+            let report = room.write().await.close(None).await;
+
+            assert_eq!(report.clean, ["healthy"]);
+            assert!(matches!(&report.unclean[..], [(id, _)] if id == "dead"));
+            assert!(!report.is_clean());
+            assert!(closed(&written));
+            assert!(room.read().await.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normal::{Broadcaster, Config, Heartbeat};
+    use axum_8_4::{Router, routing::get, extract::{State, ws::{CloseFrame, WebSocketUpgrade}}};
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, sync::RwLock};
+
+    fn pending(outbox: &Outbox<u32>) -> Vec<u32> {
+        outbox.0.lock().messages.iter().copied().collect()
+    }
+
+    fn full(messages: &[u32]) -> Outbox<u32> {
+        let outbox = Outbox::new(messages.len());
+
+        for message in messages {
+            assert!(outbox.offer("test", *message, Overflow::Reject).unwrap());
+        }
+
+        outbox
+    }
+
+    #[test]
+    fn offer_rejects_when_full() {
+        let outbox = full(&[1, 2]);
+
+        assert!(matches!(outbox.offer("test", 3, Overflow::Reject), Err(Error::QueueFull(_))));
+        assert_eq!(pending(&outbox), [1, 2]);
+        assert_eq!(outbox.dropped(), 0);
+    }
+
+    #[test]
+    fn offer_drops_the_oldest() {
+        let outbox = full(&[1, 2]);
+
+        assert!(outbox.offer("test", 3, Overflow::DropOldest).unwrap());
+        assert_eq!(pending(&outbox), [2, 3]);
+        assert_eq!(outbox.dropped(), 1);
+    }
+
+    #[test]
+    fn offer_drops_the_newest() {
+        let outbox = full(&[1, 2]);
+
+        assert!(!outbox.offer("test", 3, Overflow::DropNewest).unwrap());
+        assert_eq!(pending(&outbox), [1, 2]);
+        assert_eq!(outbox.dropped(), 1);
+    }
+
+    #[test]
+    fn offer_coalesces() {
+        let outbox = full(&[1, 2, 4]);
+
+        assert!(outbox.offer("test", 5, Overflow::Coalesce(|message| message % 2 == 0)).unwrap());
+        assert_eq!(pending(&outbox), [1, 5]);
+        assert_eq!(outbox.dropped(), 2);
+
+        // nothing can be coalesced, so the oldest one is dropped.
+        let outbox = full(&[1, 3]);
+
+        assert!(outbox.offer("test", 5, Overflow::Coalesce(|message| message % 2 == 0)).unwrap());
+        assert_eq!(pending(&outbox), [3, 5]);
+        assert_eq!(outbox.dropped(), 1);
+    }
+
+    #[test]
+    fn offer_closes() {
+        let outbox = full(&[1, 2]);
+
+        assert!(matches!(outbox.offer("test", 3, Overflow::Close(0)), Err(Error::QueueFull(_))));
+        assert_eq!(pending(&outbox), [0]);
+        assert_eq!(outbox.dropped(), 3);
+        assert!(matches!(outbox.offer("test", 4, Overflow::DropOldest), Err(Error::Closed(_))));
+    }
+
+    fn backlog(count: u32, capacity: usize, max_age: Option<Duration>) -> Backlog<u32> {
+        let backlog = Backlog::default();
+        let history = History { capacity, max_age, replay: 0 };
+
+        for _ in 0..count {
+            backlog.record(|sequence| sequence as u32, Some(&history));
+        }
+
+        backlog
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recent_gives_the_last_messages_within_the_max_age() {
+        let history = History { capacity: 3, max_age: Some(Duration::from_secs(2)), replay: 0 };
+        let backlog = backlog(5, history.capacity, history.max_age);
+
+        assert_eq!(backlog.recent(2, None), [4, 5]);
+        assert_eq!(backlog.recent(10, None), [3, 4, 5]);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+
+        backlog.record(|sequence| sequence as u32, Some(&history));
+
+        tokio::time::advance(Duration::from_millis(1500)).await;
+
+        assert_eq!(backlog.recent(10, history.max_age), [6]);
+        assert_eq!(backlog.sequence(), 6);
+    }
+
+    #[test]
+    fn recent_is_empty_without_history() {
+        let backlog = Backlog::default();
+
+        backlog.record(|sequence| sequence as u32, None);
+
+        assert!(backlog.recent(10, None).is_empty());
+        assert_eq!(backlog.sequence(), 1);
+    }
+
+    #[test]
+    fn since_gives_the_missed_messages() {
+        let backlog = backlog(5, 3, None);
+
+        assert_eq!(backlog.since(2, None), Ok(vec![3, 4, 5]));
+        assert_eq!(backlog.since(4, None), Ok(vec![5]));
+        assert_eq!(backlog.since(5, None), Ok(vec![]));
+    }
+
+    #[test]
+    fn since_reports_the_gap() {
+        let backlog = backlog(5, 3, None);
+
+        assert_eq!(backlog.since(1, None), Err(3));
+        assert_eq!(backlog.since(0, None), Err(3));
+
+        // a sequence number from the future belongs to an older room with the same id.
+        assert_eq!(backlog.since(6, None), Err(3));
+    }
+
+    #[test]
+    fn since_on_an_empty_backlog() {
+        let backlog = Backlog::<u32>::default();
+
+        assert_eq!(backlog.since(0, None), Ok(vec![]));
+        assert_eq!(backlog.since(1, None), Err(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn since_forgets_the_expired_messages() {
+        let backlog = backlog(3, 10, Some(Duration::from_secs(1)));
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+
+        assert_eq!(backlog.since(3, None), Ok(vec![]));
+        assert_eq!(backlog.since(2, Some(Duration::from_secs(1))), Err(4));
+    }
+
+    #[test]
+    fn quotas_count_the_permits() {
+        let quotas = Quotas::default();
+        let first = quotas.acquire(Some("a".to_string()), Some(2), Some(1)).unwrap();
+
+        assert!(matches!(quotas.acquire(Some("a".to_string()), Some(2), Some(1)), Err(Error::QuotaExceeded(Some(key))) if key == "a"));
+
+        let second = quotas.acquire(None, Some(2), Some(1)).unwrap();
+
+        assert!(matches!(quotas.acquire(Some("b".to_string()), Some(2), Some(1)), Err(Error::QuotaExceeded(None))));
+        assert_eq!(quotas.total(), 2);
+        assert_eq!(quotas.count("a"), 1);
+
+        drop(first);
+
+        assert_eq!(quotas.total(), 1);
+        assert_eq!(quotas.count("a"), 0);
+        assert!(quotas.acquire(Some("a".to_string()), Some(2), Some(1)).is_ok());
+
+        drop(second);
+
+        assert_eq!(quotas.total(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_refills_up_to_the_burst() {
+        let mut bucket = Bucket::new();
+
+        assert!(bucket.take(2.0, 2));
+        assert!(bucket.take(2.0, 2));
+        assert!(!bucket.take(2.0, 2));
+
+        tokio::time::advance(Duration::from_millis(250)).await;
+
+        assert!(!bucket.take(2.0, 2));
+
+        tokio::time::advance(Duration::from_millis(250)).await;
+
+        assert!(bucket.take(2.0, 2));
+        assert!(!bucket.take(2.0, 2));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+
+        assert!(bucket.take(2.0, 2));
+        assert!(bucket.take(2.0, 2));
+        assert!(!bucket.take(2.0, 2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn liveness_counts_missed_pings_and_latency() {
+        let liveness = Liveness::default();
+        let (missed, first) = liveness.ping();
+
+        assert_eq!(missed, 0);
+        assert_eq!(liveness.ping().0, 1);
+        assert_eq!(liveness.missed(), 1);
+
+        tokio::time::advance(Duration::from_millis(30)).await;
+
+        // the pong of an older ping shows that the client is alive, but it doesn't tell the latency.
+        liveness.pong(&first);
+
+        assert_eq!(liveness.missed(), 0);
+        assert_eq!(liveness.latency(), None);
+        assert_eq!(liveness.last_pong(), Some(Instant::now()));
+
+        let (missed, third) = liveness.ping();
+
+        assert_eq!(missed, 0);
+
+        tokio::time::advance(Duration::from_millis(40)).await;
+
+        liveness.pong(&third);
+
+        assert_eq!(liveness.latency(), Some(Duration::from_millis(40)));
+        assert_eq!(liveness.missed(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_closes_a_stale_connection() {
+        let broadcaster: Arc<RwLock<Broadcaster>> = Broadcaster::with_config(Config {
+            heartbeat: Some(Heartbeat {
+                interval: Duration::from_secs(1),
+                max_missed: 2,
+                close_frame: Some(CloseFrame { code: 4000, reason: "stale".into() })
+            }),
+            ..Default::default()
+        });
+
+        let router = Router::new().route("/", get(|ws: WebSocketUpgrade, State(broadcaster): State<Arc<RwLock<Broadcaster>>>| async move {
+            ws.on_upgrade(move |socket| async move {
+                let _ = Broadcaster::serve(&broadcaster, socket, "room", "stale", |_, _| async {}).await;
+            })
+        })).with_state(Arc::clone(&broadcaster));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move { axum_8_4::serve(listener, router).await.unwrap() });
+
+        // the client only does the handshake and reads, so it never answers the pings.
+        let mut socket = TcpStream::connect(address).await.unwrap();
+
+        socket.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").await.unwrap();
+
+        let mut received = vec![];
+
+        socket.read_to_end(&mut received).await.unwrap();
+
+        let handshake = received.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+
+        assert!(received.starts_with(b"HTTP/1.1 101"));
+
+        let mut frames = vec![];
+        let mut rest = &received[handshake..];
+
+        // frames of the server are not masked and these ones are short, so their length is in the second byte.
+        while let [first, second, tail @ ..] = rest {
+            let (payload, tail) = tail.split_at(*second as usize);
+
+            frames.push((first & 0x0f, payload.to_vec()));
+            rest = tail;
+        }
+
+        let pings = frames.iter().filter(|(opcode, _)| *opcode == 0x9).count();
+        let (opcode, close) = frames.last().unwrap();
+
+        assert_eq!(pings, 2);
+        assert_eq!(*opcode, 0x8);
+        assert_eq!(close[..2], 4000u16.to_be_bytes());
+        assert_eq!(&close[2..], b"stale");
+        assert!(broadcaster.read().await.rooms_of("stale").is_empty());
+    }
+}